@@ -3,16 +3,74 @@
 //! Contains a clocking that ticks in a fixed interval as precisely as
 //! possible.
 
-// FIXME: clock should start immediately, not waiting the initial interval
+mod fixed_step;
 
-use std::{iter, thread, time};
+use crate::sleep::{MeasuredSleep, SleepAccuracy, SleepStrategy};
+use crate::time_source::{Sleeper, SystemClock, TimeSource};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::{iter, time};
+
+pub use self::fixed_step::FixedStep;
 
 /// Clock structure.
-pub struct Clock {
+pub struct Clock<S = SystemClock> {
     /// Start time of the clock, in ns since epoch
     started_at: time::Instant,
     /// Tick length
     tick_len: time::Duration,
+    /// Source of time, used for reading the current time and sleeping
+    source: S,
+    /// Behavior of iterators when ticks have been missed
+    missed_tick_behavior: MissedTickBehavior,
+    /// Mapping from real time to clock time, changed by pausing and scaling
+    timeline: Mutex<Timeline>,
+    /// Strategy used for waiting on ticks, along with its accuracy
+    sleep: MeasuredSleep,
+    /// Whether iterators return the current tick right away
+    immediate_start: bool,
+}
+
+/// Mapping from real time to clock time
+#[derive(Clone, Copy, Debug)]
+struct Timeline {
+    /// Speed of the clock relative to real time
+    time_scale: f64,
+    /// Instant the time scale or pause state last changed
+    rebased_at: time::Instant,
+    /// Clock time elapsed at `rebased_at`
+    rebased_elapsed: time::Duration,
+    /// Instant the clock was paused at, if paused
+    paused_at: Option<time::Instant>,
+}
+
+/// Behavior when ticks are missed.
+///
+/// Ticks are missed when more than a full tick length passes between two
+/// iterations of a clock iterator, e.g. because a frame took too long to
+/// render.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MissedTickBehavior {
+    /// Drop missed ticks and wait for the next regular tick.
+    ///
+    /// This is the default. Tick numbers of dropped ticks are skipped, the
+    /// number of dropped ticks is available through `ClockIter::missed_ticks`.
+    Skip,
+    /// Return missed ticks immediately, one after another, until caught up.
+    ///
+    /// Useful for fixed-step simulations that must process every tick.
+    Burst,
+    /// Return the first missed tick immediately and delay all following ticks.
+    ///
+    /// The clock is restarted from the late tick, keeping a full tick length
+    /// between it and the next tick. No tick numbers are skipped.
+    Delay,
+}
+
+impl Default for MissedTickBehavior {
+    #[inline]
+    fn default() -> Self {
+        MissedTickBehavior::Skip
+    }
 }
 
 /// A clock iterator
@@ -28,7 +86,8 @@ pub struct Clock {
 /// let mut clock = Clock::new(time::Duration::from_secs(1));
 ///
 /// // as soon as the clock starts, it will wait for the next tick.
-/// // in this case, we'll start at t = 1 second
+/// // in this case, we'll start at t = 1 second. see
+/// // `Clock::with_immediate_start` for starting at t = 0 instead
 /// for tick in clock.iter() {
 ///     // ...
 ///
@@ -40,7 +99,29 @@ pub struct Clock {
 ///
 /// assert!(time::Duration::from_secs(1) < end - start);
 /// ```
-pub struct ClockIter<'a>(&'a Clock);
+pub struct ClockIter<'a, S = SystemClock> {
+    /// Underlying clock
+    clock: &'a Clock<S>,
+    /// Tick bookkeeping
+    state: TickState,
+}
+
+/// Tick bookkeeping of clock iterators and streams
+#[derive(Debug)]
+pub(crate) struct TickState {
+    /// Amount all ticks are shifted back by, grows when ticks are delayed
+    delay: time::Duration,
+    /// Number of the last tick returned
+    last_tick: u128,
+    /// Ticks dropped before the last tick returned
+    missed: u128,
+    /// Ticks dropped since the state was created
+    total_missed: u128,
+    /// Whether the tick in `last_tick` still has to be returned
+    pending: bool,
+    /// Instant of the last tick returned, used for pacing while paused
+    last_instant: time::Instant,
+}
 
 impl Clock {
     /// Creates a new clock.
@@ -54,10 +135,7 @@ impl Clock {
     /// Creates a new clock with a specified start time
     #[inline]
     pub fn new_with_start_time(tick_len: time::Duration, start: time::Instant) -> Clock {
-        Clock {
-            started_at: start,
-            tick_len,
-        }
+        Clock::new_with_source_and_start_time(tick_len, start, SystemClock)
     }
 
     /// Creates a new fixed-framerate clock
@@ -73,14 +151,86 @@ impl Clock {
 
         Clock::new_with_start_time(time::Duration::from_secs_f64(frame_time_s), start)
     }
+}
+
+impl<S> Clock<S>
+where
+    S: TimeSource,
+{
+    /// Creates a new clock using a custom time source
+    #[inline]
+    pub fn new_with_source(tick_len: time::Duration, source: S) -> Clock<S> {
+        let start = source.now();
+        Clock::new_with_source_and_start_time(tick_len, start, source)
+    }
+
+    /// Creates a new clock using a custom time source with a specified start
+    /// time
+    #[inline]
+    pub fn new_with_source_and_start_time(
+        tick_len: time::Duration,
+        start: time::Instant,
+        source: S,
+    ) -> Clock<S> {
+        Clock {
+            started_at: start,
+            tick_len,
+            source,
+            missed_tick_behavior: MissedTickBehavior::default(),
+            timeline: Mutex::new(Timeline {
+                time_scale: 1.0,
+                rebased_at: start,
+                rebased_elapsed: time::Duration::from_secs(0),
+                paused_at: None,
+            }),
+            sleep: MeasuredSleep::default(),
+            immediate_start: false,
+        }
+    }
+
+    /// Sets the behavior of clock iterators when ticks are missed
+    #[inline]
+    pub fn with_missed_tick_behavior(mut self, behavior: MissedTickBehavior) -> Clock<S> {
+        self.missed_tick_behavior = behavior;
+        self
+    }
+
+    /// Sets the strategy used for waiting on ticks
+    ///
+    /// See the `sleep` module.
+    #[inline]
+    pub fn with_sleep_strategy(mut self, strategy: SleepStrategy) -> Clock<S> {
+        self.sleep = MeasuredSleep::new(strategy);
+        self
+    }
+
+    /// Sets whether iterators return the current tick right away
+    ///
+    /// By default, iterators and streams wait for the next tick before
+    /// returning anything. With immediate start, the tick in progress when
+    /// the iterator is created is returned first, without waiting, i.e. tick
+    /// 0 at the start time for a fresh clock. `wait_until_tick` is unaffected.
+    #[inline]
+    pub fn with_immediate_start(mut self, immediate_start: bool) -> Clock<S> {
+        self.immediate_start = immediate_start;
+        self
+    }
 
     /// Creates a new clock with a different tick length that is synced to
     /// the original clock
     #[inline]
-    pub fn synced(&self, tick_len: time::Duration) -> Clock {
+    pub fn synced(&self, tick_len: time::Duration) -> Clock<S>
+    where
+        S: Clone,
+    {
         Clock {
             started_at: self.started_at,
             tick_len,
+            source: self.source.clone(),
+            missed_tick_behavior: self.missed_tick_behavior,
+            timeline: Mutex::new(self.timeline()),
+            sleep: MeasuredSleep::new(self.sleep.strategy()),
+            immediate_start: self.immediate_start,
         }
     }
 
@@ -90,41 +240,202 @@ impl Clock {
         self.started_at
     }
 
+    /// Get the behavior of clock iterators when ticks are missed
+    #[inline]
+    pub fn missed_tick_behavior(&self) -> MissedTickBehavior {
+        self.missed_tick_behavior
+    }
+
+    /// Check whether iterators return the current tick right away
+    #[inline]
+    pub fn immediate_start(&self) -> bool {
+        self.immediate_start
+    }
+
+    /// Get the time source
+    #[inline]
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// Get the strategy used for waiting on ticks
+    #[inline]
+    pub fn sleep_strategy(&self) -> SleepStrategy {
+        self.sleep.strategy()
+    }
+
+    /// Returns the accuracy of all waits on ticks so far
+    ///
+    /// Includes waits of all iterators of this clock.
+    #[inline]
+    pub fn sleep_accuracy(&self) -> SleepAccuracy {
+        self.sleep.accuracy()
+    }
+
+    /// Get the speed of the clock relative to real time
+    #[inline]
+    pub fn time_scale(&self) -> f64 {
+        self.timeline().time_scale
+    }
+
+    /// Changes the speed of the clock relative to real time
+    ///
+    /// A scale of 2 halves the real time between two ticks (fast forward), a
+    /// scale of 0.5 doubles it (slow motion). The current tick number is
+    /// unaffected by the change.
+    ///
+    /// Panics if `scale` is not a positive, finite number.
+    #[inline]
+    pub fn set_time_scale(&self, scale: f64, now: time::Instant) {
+        assert!(
+            scale > 0.0 && scale.is_finite(),
+            "time scale must be positive and finite"
+        );
+
+        let mut timeline = self.lock_timeline();
+        timeline.rebase(now);
+        timeline.time_scale = scale;
+    }
+
+    /// Pauses the clock
+    ///
+    /// Tick numbers do not advance while the clock is paused. Waiting on or
+    /// iterating a paused clock still returns one tick per tick length of
+    /// real time, repeating the current tick number, so loops driven by the
+    /// clock keep running at their usual pace. Does nothing if the clock is
+    /// already paused.
+    #[inline]
+    pub fn pause(&self, now: time::Instant) {
+        let mut timeline = self.lock_timeline();
+        if timeline.paused_at.is_none() {
+            timeline.paused_at = Some(now);
+        }
+    }
+
+    /// Resumes a paused clock
+    ///
+    /// All following ticks are shifted back by the time spent paused. Does
+    /// nothing if the clock is not paused.
+    #[inline]
+    pub fn resume(&self, now: time::Instant) {
+        let mut timeline = self.lock_timeline();
+        if timeline.paused_at.is_some() {
+            timeline.rebase(now);
+            timeline.paused_at = None;
+        }
+    }
+
+    /// Check whether the clock is paused
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.timeline().paused_at.is_some()
+    }
+
+    /// Returns the clock time passed between the start of the clock and `now`
+    ///
+    /// Clock time does not pass while paused and is scaled by the time scale.
+    #[inline]
+    pub fn elapsed_at(&self, now: time::Instant) -> time::Duration {
+        self.timeline().elapsed_at(now)
+    }
+
     /// Returns the tick number preceding an specific instant in time
     #[inline]
     pub fn tick_num_at(&self, now: time::Instant) -> u128 {
-        (now - self.started_at).as_nanos() / self.tick_len.as_nanos()
+        self.timeline().tick_num_at(now, self.tick_len)
+    }
+
+    /// Returns the instant at which tick number `tick_num` is due
+    ///
+    /// Ticks before the last pause or change of time scale are extrapolated
+    /// using the current time scale. Returns `None` while the clock is
+    /// paused, as no tick is due then.
+    #[inline]
+    pub fn tick_instant(&self, tick_num: u128) -> Option<time::Instant> {
+        let timeline = self.timeline();
+
+        if timeline.paused_at.is_some() {
+            return None;
+        }
+
+        Some(timeline.tick_instant(tick_num, self.tick_len))
+    }
+
+    /// Returns a snapshot of the mapping from real time to clock time
+    #[inline]
+    fn timeline(&self) -> Timeline {
+        *self.lock_timeline()
     }
 
+    /// Locks the mapping from real time to clock time for changes
+    #[inline]
+    fn lock_timeline(&self) -> MutexGuard<'_, Timeline> {
+        // the timeline is plain data and valid even if a holder panicked
+        self.timeline.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Creates a clock stream.
+    ///
+    /// Asynchronous version of `iter()`, waiting for ticks using timers of
+    /// type `T` instead of blocking the thread. See the `stream` module for
+    /// available timers.
+    #[cfg(any(feature = "tokio", feature = "async-std"))]
+    #[inline]
+    pub fn stream<T>(&self) -> crate::stream::ClockStream<'_, T, S>
+    where
+        T: crate::stream::AsyncTimer,
+    {
+        crate::stream::ClockStream::new(self)
+    }
+}
+
+impl<S> Clock<S>
+where
+    S: Sleeper,
+{
     /// Waits for the next clock tick.
     ///
-    /// Will wait until the next tick and return the current tick count.
+    /// Will wait until the next tick and return the current tick count. Missed
+    /// ticks are always skipped, regardless of the configured
+    /// `MissedTickBehavior`, as no state is kept between calls.
+    ///
+    /// While the clock is paused, waits for one tick length of real time and
+    /// returns the current tick number, which does not advance.
     #[inline]
     pub fn wait_until_tick(&self) -> (u128, time::Instant) {
-        // uses signed math because ntp might put us in the past
-        let now = time::Instant::now();
+        let now = self.source.now();
+        let timeline = self.timeline();
 
-        let current_tick_num = self.tick_num_at(now);
-        let next_tick_num = current_tick_num + 1;
+        let current_tick_num = timeline.tick_num_at(now, self.tick_len);
+
+        if timeline.paused_at.is_some() {
+            let next_tick = now + self.tick_len;
+            self.sleep.sleep_until(&self.source, next_tick);
+            return (current_tick_num, next_tick);
+        }
 
-        let next_tick = self.started_at + self.tick_len * next_tick_num as u32;
-        let until_next: time::Duration = next_tick - now;
+        let next_tick_num = current_tick_num + 1;
+        let next_tick = timeline.tick_instant(next_tick_num, self.tick_len);
 
-        thread::sleep(until_next);
+        self.sleep.sleep_until(&self.source, next_tick);
         (next_tick_num, next_tick)
     }
 
     /// Creates a clock iterator.
     ///
-    /// The iterator will iterate forever, calling `wait_until_tick` on each
-    /// iteration. It will panic after about 293 years.
+    /// The iterator will iterate forever, waiting for the next tick on each
+    /// iteration. It will panic after about 293 years. Ticks missed while
+    /// iterating are handled according to the clock's `MissedTickBehavior`.
     ///
     /// Returns (current tick number, absolute time) on each iteration, where
     /// absolute time is relative to a fixed offset that depends on the machine
     /// (see `Instant`).
     #[inline]
-    pub fn iter(&self) -> ClockIter {
-        ClockIter(self)
+    pub fn iter(&self) -> ClockIter<'_, S> {
+        ClockIter {
+            clock: self,
+            state: TickState::new(self),
+        }
     }
 
     /// Create a relative clock iterator.
@@ -133,17 +444,243 @@ impl Clock {
     /// (current tick number, relative time), with relative time being a
     /// `time::Duration` from the start of the clock.
     #[inline]
-    pub fn rel_iter(&self) -> ClockIterRelative {
-        ClockIterRelative(self)
+    pub fn rel_iter(&self) -> ClockIterRelative<'_, S> {
+        ClockIterRelative(self.iter())
     }
 }
 
-impl<'a> iter::Iterator for ClockIter<'a> {
+impl<'a, S> ClockIter<'a, S> {
+    /// Number of ticks dropped before the most recently returned tick
+    ///
+    /// Only `MissedTickBehavior::Skip` drops ticks, with other behaviors this
+    /// is always zero.
+    #[inline]
+    pub fn missed_ticks(&self) -> u128 {
+        self.state.missed_ticks()
+    }
+
+    /// Total number of ticks dropped since the iterator was created
+    #[inline]
+    pub fn total_missed_ticks(&self) -> u128 {
+        self.state.total_missed_ticks()
+    }
+}
+
+impl<'a, S> iter::Iterator for ClockIter<'a, S>
+where
+    S: Sleeper,
+{
     type Item = (u128, time::Instant);
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        Some(self.0.wait_until_tick())
+        let now = self.clock.source.now();
+        let (tick_num, tick) = self.state.schedule(self.clock, now);
+
+        self.clock.sleep.sleep_until(&self.clock.source, tick);
+
+        Some((tick_num, tick))
+    }
+}
+
+impl TickState {
+    /// Creates new bookkeeping, starting at the current tick of `clock`
+    #[inline]
+    pub(crate) fn new<S: TimeSource>(clock: &Clock<S>) -> TickState {
+        let now = clock.source.now();
+
+        TickState {
+            delay: time::Duration::from_secs(0),
+            last_tick: clock.tick_num_at(now),
+            missed: 0,
+            total_missed: 0,
+            pending: clock.immediate_start,
+            last_instant: now,
+        }
+    }
+
+    /// Number of ticks dropped before the most recently scheduled tick
+    #[inline]
+    pub(crate) fn missed_ticks(&self) -> u128 {
+        self.missed
+    }
+
+    /// Total number of ticks dropped
+    #[inline]
+    pub(crate) fn total_missed_ticks(&self) -> u128 {
+        self.total_missed
+    }
+
+    /// Returns the instant of tick number `tick_num`, shifted by the delay
+    #[inline]
+    fn tick_instant<S>(
+        &self,
+        clock: &Clock<S>,
+        timeline: &Timeline,
+        tick_num: u128,
+    ) -> time::Instant {
+        timeline.tick_instant(tick_num, clock.tick_len) + self.delay
+    }
+
+    /// Determines the next tick to be returned
+    ///
+    /// Returns the tick number and the instant at which it is due, which may
+    /// lie in the past if missed ticks are being caught up on.
+    ///
+    /// While the clock is paused, the last tick number is repeated once per
+    /// tick length of real time.
+    pub(crate) fn schedule<S: TimeSource>(
+        &mut self,
+        clock: &Clock<S>,
+        now: time::Instant,
+    ) -> (u128, time::Instant) {
+        // a single snapshot, so concurrent changes do not mix in halfway
+        let timeline = clock.timeline();
+        let (tick_num, tick) = self.next_tick(clock, &timeline, now);
+        self.last_instant = tick;
+        (tick_num, tick)
+    }
+
+    /// Determines the next tick, see `schedule`
+    fn next_tick<S>(
+        &mut self,
+        clock: &Clock<S>,
+        timeline: &Timeline,
+        now: time::Instant,
+    ) -> (u128, time::Instant) {
+        let paused = timeline.paused_at.is_some();
+
+        if self.pending {
+            self.pending = false;
+
+            let tick = if paused {
+                now
+            } else {
+                self.tick_instant(clock, timeline, self.last_tick)
+            };
+            return (self.last_tick, tick);
+        }
+
+        if paused {
+            self.missed = 0;
+            return (
+                self.last_tick,
+                (self.last_instant + clock.tick_len).max(now),
+            );
+        }
+
+        let mut tick_num = self.last_tick + 1;
+        let mut tick = self.tick_instant(clock, timeline, tick_num);
+        self.missed = 0;
+
+        if tick < now {
+            match clock.missed_tick_behavior {
+                MissedTickBehavior::Skip => {
+                    let next_tick_num = timeline.tick_num_at(now - self.delay, clock.tick_len) + 1;
+
+                    self.missed = next_tick_num - tick_num;
+                    self.total_missed += self.missed;
+
+                    tick_num = next_tick_num;
+                    tick = self.tick_instant(clock, timeline, tick_num);
+                }
+                MissedTickBehavior::Burst => {
+                    // return the missed tick right away
+                }
+                MissedTickBehavior::Delay => {
+                    // shift all future ticks back by the amount we are late
+                    self.delay += now - tick;
+                    tick = now;
+                }
+            }
+        }
+
+        self.last_tick = tick_num;
+        (tick_num, tick)
+    }
+}
+
+/// Calculates `duration * n`, saturating at the maximum duration.
+///
+/// Unlike multiplying a `Duration` directly, this does not truncate `n` and
+/// works for tick numbers of arbitrary size.
+#[inline]
+pub(crate) fn duration_multiple(duration: time::Duration, n: u128) -> time::Duration {
+    let nanos = duration.as_nanos().saturating_mul(n);
+    let secs = nanos / 1_000_000_000;
+
+    if secs > u64::MAX as u128 {
+        return time::Duration::MAX;
+    }
+
+    time::Duration::new(secs as u64, (nanos % 1_000_000_000) as u32)
+}
+
+impl Timeline {
+    /// Returns the clock time passed between the start of the clock and `now`
+    #[inline]
+    fn elapsed_at(&self, now: time::Instant) -> time::Duration {
+        let now = match self.paused_at {
+            Some(paused_at) => paused_at.min(now),
+            None => now,
+        };
+
+        self.rebased_elapsed
+            + scale_duration(
+                now.saturating_duration_since(self.rebased_at),
+                self.time_scale,
+            )
+    }
+
+    /// Returns the tick number preceding `now`
+    #[inline]
+    fn tick_num_at(&self, now: time::Instant, tick_len: time::Duration) -> u128 {
+        self.elapsed_at(now).as_nanos() / tick_len.as_nanos()
+    }
+
+    /// Returns the instant at which tick number `tick_num` is due, ignoring
+    /// a pause
+    #[inline]
+    fn tick_instant(&self, tick_num: u128, tick_len: time::Duration) -> time::Instant {
+        let elapsed = duration_multiple(tick_len, tick_num);
+
+        if elapsed >= self.rebased_elapsed {
+            self.rebased_at + unscale_duration(elapsed - self.rebased_elapsed, self.time_scale)
+        } else {
+            self.rebased_at - unscale_duration(self.rebased_elapsed - elapsed, self.time_scale)
+        }
+    }
+
+    /// Restarts the mapping from real time to clock time at `now`.
+    #[inline]
+    fn rebase(&mut self, now: time::Instant) {
+        self.rebased_elapsed = self.elapsed_at(now);
+        self.rebased_at = now;
+
+        if self.paused_at.is_some() {
+            self.paused_at = Some(now);
+        }
+    }
+}
+
+/// Scales a duration of real time to clock time.
+#[inline]
+fn scale_duration(duration: time::Duration, scale: f64) -> time::Duration {
+    // avoid rounding errors at normal speed
+    if scale == 1.0 {
+        duration
+    } else {
+        duration.mul_f64(scale)
+    }
+}
+
+/// Scales a duration of clock time to real time.
+#[inline]
+fn unscale_duration(duration: time::Duration, scale: f64) -> time::Duration {
+    if scale == 1.0 {
+        duration
+    } else {
+        duration.div_f64(scale)
     }
 }
 
@@ -151,14 +688,277 @@ impl<'a> iter::Iterator for ClockIter<'a> {
 ///
 /// The resulting returned tuple will be of the form `(tick_number,
 /// duration_since_clock_start)`
-pub struct ClockIterRelative<'a>(&'a Clock);
+pub struct ClockIterRelative<'a, S = SystemClock>(ClockIter<'a, S>);
 
-impl<'a> iter::Iterator for ClockIterRelative<'a> {
+impl<'a, S> ClockIterRelative<'a, S> {
+    /// Number of ticks dropped before the most recently returned tick
+    ///
+    /// See `ClockIter::missed_ticks`.
+    #[inline]
+    pub fn missed_ticks(&self) -> u128 {
+        self.0.missed_ticks()
+    }
+
+    /// Total number of ticks dropped since the iterator was created
+    #[inline]
+    pub fn total_missed_ticks(&self) -> u128 {
+        self.0.total_missed_ticks()
+    }
+}
+
+impl<'a, S> iter::Iterator for ClockIterRelative<'a, S>
+where
+    S: Sleeper,
+{
     type Item = (u128, time::Duration);
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        let (n, t) = self.0.wait_until_tick();
-        Some((n, t - self.0.started_at))
+        let (n, t) = self.0.next()?;
+        Some((n, t - self.0.clock.started_at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time_source::MockClock;
+
+    #[test]
+    fn ticks_on_mock_clock() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone());
+
+        let ticks: Vec<_> = clock.iter().take(3).collect();
+        assert_eq!(
+            ticks,
+            vec![
+                (1, start + time::Duration::from_millis(10)),
+                (2, start + time::Duration::from_millis(20)),
+                (3, start + time::Duration::from_millis(30)),
+            ]
+        );
+        assert_eq!(mock.now() - start, time::Duration::from_millis(30));
+    }
+
+    #[test]
+    fn skips_ticks_after_overrun() {
+        let mock = MockClock::new();
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone());
+
+        mock.advance(time::Duration::from_millis(35));
+        let (tick, _) = clock.wait_until_tick();
+        assert_eq!(tick, 4);
+
+        let (tick, rel) = clock.rel_iter().next().unwrap();
+        assert_eq!(tick, 5);
+        assert_eq!(rel, time::Duration::from_millis(50));
+    }
+
+    #[test]
+    fn iter_reports_skipped_ticks() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone());
+        let mut ticks = clock.iter();
+
+        assert_eq!(ticks.next().unwrap().0, 1);
+        assert_eq!(ticks.missed_ticks(), 0);
+
+        mock.advance(time::Duration::from_millis(35));
+        assert_eq!(
+            ticks.next().unwrap(),
+            (5, start + time::Duration::from_millis(50))
+        );
+        assert_eq!(ticks.missed_ticks(), 3);
+
+        assert_eq!(ticks.next().unwrap().0, 6);
+        assert_eq!(ticks.missed_ticks(), 0);
+        assert_eq!(ticks.total_missed_ticks(), 3);
+    }
+
+    #[test]
+    fn iter_bursts_missed_ticks() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone())
+            .with_missed_tick_behavior(MissedTickBehavior::Burst);
+        let mut ticks = clock.iter();
+
+        mock.advance(time::Duration::from_millis(35));
+        for n in 1..=3 {
+            assert_eq!(
+                ticks.next().unwrap(),
+                (n, start + time::Duration::from_millis(10 * n as u64))
+            );
+            assert_eq!(mock.now() - start, time::Duration::from_millis(35));
+        }
+
+        assert_eq!(
+            ticks.next().unwrap(),
+            (4, start + time::Duration::from_millis(40))
+        );
+        assert_eq!(ticks.total_missed_ticks(), 0);
+    }
+
+    #[test]
+    fn iter_delays_after_missed_ticks() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone())
+            .with_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut ticks = clock.rel_iter();
+
+        mock.advance(time::Duration::from_millis(35));
+        assert_eq!(ticks.next().unwrap(), (1, time::Duration::from_millis(35)));
+        assert_eq!(ticks.next().unwrap(), (2, time::Duration::from_millis(45)));
+        assert_eq!(mock.now() - start, time::Duration::from_millis(45));
+        assert_eq!(ticks.total_missed_ticks(), 0);
+    }
+
+    #[test]
+    fn starts_immediately() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone())
+            .with_immediate_start(true);
+
+        let ticks: Vec<_> = clock.rel_iter().take(3).collect();
+        assert_eq!(
+            ticks,
+            vec![
+                (0, time::Duration::from_millis(0)),
+                (1, time::Duration::from_millis(10)),
+                (2, time::Duration::from_millis(20)),
+            ]
+        );
+
+        // later iterators start with the tick in progress
+        mock.advance(time::Duration::from_millis(5));
+        let mut ticks = clock.iter();
+        assert_eq!(
+            ticks.next().unwrap(),
+            (2, start + time::Duration::from_millis(20))
+        );
+        assert_eq!(mock.now() - start, time::Duration::from_millis(25));
+        assert_eq!(ticks.next().unwrap().0, 3);
+        assert_eq!(ticks.total_missed_ticks(), 0);
+    }
+
+    #[test]
+    fn measures_sleep_accuracy() {
+        let mock = MockClock::new();
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone())
+            .with_sleep_strategy(SleepStrategy::YieldSpin);
+
+        assert_eq!(clock.sleep_strategy(), SleepStrategy::YieldSpin);
+        assert_eq!(clock.iter().take(3).count(), 3);
+
+        let accuracy = clock.sleep_accuracy();
+        assert_eq!(accuracy.sleeps(), 3);
+        assert_eq!(accuracy.max_overshoot(), time::Duration::from_secs(0));
+    }
+
+    #[test]
+    fn pause_shifts_ticks() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let ms = |n| start + time::Duration::from_millis(n);
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone());
+
+        clock.pause(ms(15));
+        assert!(clock.is_paused());
+        assert_eq!(clock.tick_num_at(ms(1000)), 1);
+        assert_eq!(clock.tick_instant(2), None);
+
+        clock.resume(ms(1000));
+        assert_eq!(clock.tick_num_at(ms(1004)), 1);
+        assert_eq!(clock.tick_instant(2), Some(ms(1005)));
+
+        mock.set(ms(1000));
+        assert_eq!(clock.wait_until_tick(), (2, ms(1005)));
+    }
+
+    #[test]
+    fn pause_while_iterating() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let ms = |n| start + time::Duration::from_millis(n);
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone());
+        let mut ticks = clock.iter();
+
+        assert_eq!(ticks.next().unwrap(), (1, ms(10)));
+
+        // the tick number stays put, but ticks keep coming in real time
+        clock.pause(ms(15));
+        assert_eq!(ticks.next().unwrap(), (1, ms(20)));
+        assert_eq!(ticks.next().unwrap(), (1, ms(30)));
+        assert_eq!(clock.wait_until_tick(), (1, ms(40)));
+
+        clock.resume(ms(40));
+        assert_eq!(ticks.next().unwrap(), (2, ms(45)));
+        assert_eq!(ticks.total_missed_ticks(), 0);
+
+        // iterators can be created while paused
+        clock.pause(ms(45));
+        assert_eq!(clock.iter().next().unwrap(), (2, ms(55)));
+    }
+
+    #[test]
+    fn ticks_past_u32_range() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let clock = Clock::new_with_source(time::Duration::from_millis(1), mock.clone());
+
+        // about 50 days at 1 kHz
+        let n = u128::from(u32::MAX) + 10;
+        assert_eq!(
+            clock.tick_instant(n),
+            Some(start + time::Duration::from_millis(n as u64))
+        );
+
+        mock.advance(time::Duration::from_micros(n as u64 * 1000 + 500));
+        assert_eq!(
+            clock.wait_until_tick(),
+            (n + 1, start + time::Duration::from_millis(n as u64 + 1))
+        );
+        assert_eq!(clock.iter().next().unwrap().0, n + 2);
+
+        assert_eq!(
+            duration_multiple(time::Duration::from_secs(u64::MAX), 2),
+            time::Duration::MAX
+        );
+    }
+
+    #[test]
+    fn time_scale_changes_tick_length() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let ms = |n| start + time::Duration::from_millis(n);
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone());
+
+        // fast forward after 25 ms, the remaining 5 ms of tick 2 take 2.5 ms
+        clock.set_time_scale(2.0, ms(25));
+        assert_eq!(clock.time_scale(), 2.0);
+        assert_eq!(clock.tick_num_at(ms(25)), 2);
+        assert_eq!(
+            clock.tick_instant(3),
+            Some(ms(25) + time::Duration::from_micros(2500))
+        );
+        assert_eq!(
+            clock.tick_instant(4),
+            Some(ms(25) + time::Duration::from_micros(7500))
+        );
+
+        // slow motion
+        clock.set_time_scale(0.5, ms(35));
+        assert_eq!(clock.tick_num_at(ms(35)), 4);
+        assert_eq!(clock.elapsed_at(ms(35)), time::Duration::from_millis(45));
+        assert_eq!(clock.tick_instant(5), Some(ms(45)));
+
+        mock.set(ms(35));
+        let ticks: Vec<_> = clock.iter().take(2).collect();
+        assert_eq!(ticks, vec![(5, ms(45)), (6, ms(65))]);
     }
 }
@@ -0,0 +1,196 @@
+//! Fixed timestep driver.
+
+use super::Clock;
+use crate::time_source::{SystemClock, TimeSource};
+use std::time;
+
+/// Default maximum number of updates per frame.
+const DEFAULT_MAX_UPDATES: u32 = 5;
+
+/// Fixed-rate updates for variable-rate loops
+///
+/// Implements the "fix your timestep" accumulator on top of a `Clock`: every
+/// tick of the clock is one fixed update. Each frame, `update` returns how
+/// many updates are due and how far into the next tick the clock is, which
+/// can be used to interpolate between the last two simulation states when
+/// rendering.
+///
+/// When more than `max_updates` are due at once, e.g. after a long stall,
+/// the excess updates are dropped instead of being caught up on. This keeps
+/// a slow simulation from falling further and further behind.
+///
+/// ```
+/// use std::time;
+/// use ticktock::clock::{Clock, FixedStep};
+/// use ticktock::time_source::MockClock;
+///
+/// let mock = MockClock::new();
+/// let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone());
+/// let mut step = FixedStep::new(clock);
+///
+/// mock.advance(time::Duration::from_millis(25));
+/// let (updates, alpha) = step.update();
+///
+/// for _ in 0..updates {
+///     // advance simulation by `step.step_len()`
+/// }
+/// // render, interpolating with `alpha`
+///
+/// assert_eq!(updates, 2);
+/// assert_eq!(alpha, 0.5);
+/// ```
+pub struct FixedStep<S = SystemClock> {
+    /// Clock driving the updates
+    clock: Clock<S>,
+    /// Tick number of the last update
+    last_tick: u128,
+    /// Maximum number of updates returned at once
+    max_updates: u32,
+    /// Updates dropped during the last call to `update`
+    dropped: u128,
+    /// Updates dropped since creation
+    total_dropped: u128,
+}
+
+impl<S> FixedStep<S>
+where
+    S: TimeSource,
+{
+    /// Creates a new driver, starting at the current tick of `clock`
+    #[inline]
+    pub fn new(clock: Clock<S>) -> FixedStep<S> {
+        let last_tick = clock.tick_num_at(clock.source.now());
+
+        FixedStep {
+            clock,
+            last_tick,
+            max_updates: DEFAULT_MAX_UPDATES,
+            dropped: 0,
+            total_dropped: 0,
+        }
+    }
+
+    /// Sets the maximum number of updates returned by a single call
+    ///
+    /// Defaults to 5. Panics if `max_updates` is zero.
+    #[inline]
+    pub fn with_max_updates(mut self, max_updates: u32) -> FixedStep<S> {
+        assert!(max_updates > 0, "max_updates must not be zero");
+
+        self.max_updates = max_updates;
+        self
+    }
+
+    /// Get the maximum number of updates returned by a single call
+    #[inline]
+    pub fn max_updates(&self) -> u32 {
+        self.max_updates
+    }
+
+    /// Get the length of a single update, in clock time
+    #[inline]
+    pub fn step_len(&self) -> time::Duration {
+        self.clock.tick_len
+    }
+
+    /// Get the underlying clock
+    #[inline]
+    pub fn clock(&self) -> &Clock<S> {
+        &self.clock
+    }
+
+    /// Get the underlying clock mutably
+    #[inline]
+    pub fn clock_mut(&mut self) -> &mut Clock<S> {
+        &mut self.clock
+    }
+
+    /// Number of updates dropped during the most recent call to `update`
+    #[inline]
+    pub fn dropped_updates(&self) -> u128 {
+        self.dropped
+    }
+
+    /// Total number of updates dropped since the driver was created
+    #[inline]
+    pub fn total_dropped_updates(&self) -> u128 {
+        self.total_dropped
+    }
+
+    /// Returns the updates due now
+    ///
+    /// See `update_at`.
+    #[inline]
+    pub fn update(&mut self) -> (u32, f64) {
+        let now = self.clock.source.now();
+        self.update_at(now)
+    }
+
+    /// Returns the updates due at `now`
+    ///
+    /// Returns the number of fixed updates to run and the interpolation
+    /// factor, i.e. the fraction of the following tick that has passed, in
+    /// the range `[0, 1)`. Does not wait.
+    pub fn update_at(&mut self, now: time::Instant) -> (u32, f64) {
+        let elapsed = self.clock.elapsed_at(now).as_nanos();
+        let tick_len = self.clock.tick_len.as_nanos();
+        let tick_num = elapsed / tick_len;
+
+        let due = tick_num.saturating_sub(self.last_tick);
+        let updates = due.min(u128::from(self.max_updates));
+
+        self.dropped = due - updates;
+        self.total_dropped += self.dropped;
+        self.last_tick = self.last_tick.max(tick_num);
+
+        let alpha = (elapsed % tick_len) as f64 / tick_len as f64;
+        (updates as u32, alpha)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time_source::MockClock;
+
+    #[test]
+    fn accumulates_ticks() {
+        let mock = MockClock::new();
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone());
+        let mut step = FixedStep::new(clock);
+
+        assert_eq!(step.update(), (0, 0.0));
+
+        mock.advance(time::Duration::from_millis(4));
+        assert_eq!(step.update(), (0, 0.4));
+
+        mock.advance(time::Duration::from_millis(8));
+        assert_eq!(step.update().0, 1);
+
+        mock.advance(time::Duration::from_millis(28));
+        assert_eq!(step.update(), (3, 0.0));
+        assert_eq!(step.dropped_updates(), 0);
+
+        // nothing happens while paused
+        let now = mock.now();
+        step.clock().pause(now);
+        mock.advance(time::Duration::from_millis(100));
+        assert_eq!(step.update(), (0, 0.0));
+    }
+
+    #[test]
+    fn clamps_updates() {
+        let mock = MockClock::new();
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone());
+        let mut step = FixedStep::new(clock).with_max_updates(4);
+
+        mock.advance(time::Duration::from_millis(1005));
+        assert_eq!(step.update(), (4, 0.5));
+        assert_eq!(step.dropped_updates(), 96);
+
+        mock.advance(time::Duration::from_millis(10));
+        assert_eq!(step.update(), (1, 0.5));
+        assert_eq!(step.dropped_updates(), 0);
+        assert_eq!(step.total_dropped_updates(), 96);
+    }
+}
@@ -2,42 +2,110 @@
 //!
 //! A simpler iterator than `clock::Clock` that delays between executions with non-adaptive
 //! intervals.
+//!
+//! `Backoff` increases the delay after every iteration instead, which is better suited for
+//! retrying failed operations.
 
-use std::{iter, thread, time};
+use crate::rng::Rng;
+use crate::sleep::{MeasuredSleep, SleepAccuracy, SleepStrategy};
+use crate::time_source::{Sleeper, SystemClock};
+use std::{iter, time};
 
 /// Simple iterable delay
 ///
 /// Iterating over this structure will insert `delay` between each iteration, starting after the
 /// first.
-pub struct Delay {
+pub struct Delay<S = SystemClock> {
     /// Delay duration
     delay: time::Duration,
 
     /// Notes whether or not we are on the first tick. Used to skip the delay on first iteration.
     first_tick: bool,
+
+    /// Time source used for sleeping.
+    source: S,
+
+    /// Strategy used for sleeping, along with its accuracy.
+    sleep: MeasuredSleep,
 }
 
 impl Delay {
     /// Creates a new delay
     #[inline]
     pub fn new(delay: time::Duration) -> Delay {
+        Delay::new_with_source(delay, SystemClock)
+    }
+
+    /// Creates a new delay that delays first
+    #[inline]
+    pub fn delayed(delay: time::Duration) -> Delay {
+        Delay::delayed_with_source(delay, SystemClock)
+    }
+}
+
+impl<S> Delay<S> {
+    /// Creates a new delay using a custom time source
+    #[inline]
+    pub fn new_with_source(delay: time::Duration, source: S) -> Delay<S> {
         Delay {
             delay,
             first_tick: true,
+            source,
+            sleep: MeasuredSleep::default(),
         }
     }
 
-    /// Creates a new delay that delays first
+    /// Creates a new delay that delays first, using a custom time source
     #[inline]
-    pub fn delayed(delay: time::Duration) -> Delay {
+    pub fn delayed_with_source(delay: time::Duration, source: S) -> Delay<S> {
         Delay {
             delay,
             first_tick: false,
+            source,
+            sleep: MeasuredSleep::default(),
         }
     }
+
+    /// Sets the strategy used for sleeping
+    ///
+    /// See the `sleep` module. Streams always use their timer instead.
+    #[inline]
+    pub fn with_sleep_strategy(mut self, strategy: SleepStrategy) -> Delay<S> {
+        self.sleep = MeasuredSleep::new(strategy);
+        self
+    }
+
+    /// Get the strategy used for sleeping
+    #[inline]
+    pub fn sleep_strategy(&self) -> SleepStrategy {
+        self.sleep.strategy()
+    }
+
+    /// Returns the accuracy of all delays so far
+    #[inline]
+    pub fn sleep_accuracy(&self) -> SleepAccuracy {
+        self.sleep.accuracy()
+    }
+
+    /// Turns the delay into a stream.
+    ///
+    /// Asynchronous version of iterating, waiting using timers of type `T`
+    /// instead of blocking the thread. See the `stream` module for available
+    /// timers.
+    #[cfg(any(feature = "tokio", feature = "async-std"))]
+    #[inline]
+    pub fn stream<T>(self) -> crate::stream::DelayStream<T, S>
+    where
+        T: crate::stream::AsyncTimer,
+    {
+        crate::stream::DelayStream::new(self.delay, self.first_tick, self.source)
+    }
 }
 
-impl<'a> iter::Iterator for Delay {
+impl<S> iter::Iterator for Delay<S>
+where
+    S: Sleeper,
+{
     type Item = ();
 
     #[inline]
@@ -45,9 +113,389 @@ impl<'a> iter::Iterator for Delay {
         if self.first_tick {
             self.first_tick = false;
         } else {
-            thread::sleep(self.delay);
+            let deadline = self.source.now() + self.delay;
+            self.sleep.sleep_until(&self.source, deadline);
         }
 
         Some(())
     }
 }
+
+/// Exponential backoff iterator
+///
+/// Like `Delay`, but the delay grows by a constant factor after every iteration, optionally capped
+/// at a maximum delay. Iteration ends once a maximum total time has been exceeded, if set.
+///
+/// Randomizing delays avoids many clients retrying in lockstep, see `Jitter`. The random number
+/// generator is seeded randomly, unless a seed is set using `with_seed`.
+///
+/// ```rust
+/// use std::net::TcpStream;
+/// use std::time::Duration;
+/// use ticktock::delay::{Backoff, Jitter};
+/// use ticktock::Attempt;
+///
+/// // retry after 100 ms, 200 ms, 400 ms, ..., but give up after one second
+/// let conn = Backoff::new(Duration::from_millis(100))
+///     .with_max_elapsed_time(Duration::from_secs(1))
+///     .with_jitter(Jitter::Equal)
+///     .map(|_| TcpStream::connect("localhost:12348"))
+///     .attempt()
+///     .unwrap();
+///
+/// # // our test will fail, because there is noting listening at 12348
+/// # assert!(conn.is_err());
+/// ```
+#[derive(Debug)]
+pub struct Backoff<S = SystemClock> {
+    /// Delay before the first retry
+    initial: time::Duration,
+
+    /// Factor the delay grows by after every retry
+    factor: f64,
+
+    /// Upper bound for a single delay
+    max_delay: Option<time::Duration>,
+
+    /// Upper bound for the total time spent, measured from the first iteration
+    max_elapsed_time: Option<time::Duration>,
+
+    /// Randomization applied to delays
+    jitter: Jitter,
+
+    /// Source of randomness for jitter
+    rng: Rng,
+
+    /// Number of delays inserted so far
+    retries: u32,
+
+    /// Most recent delay, grown from by decorrelated jitter
+    last_delay: Option<time::Duration>,
+
+    /// Instant of the first iteration, `None` if not started yet
+    started_at: Option<time::Instant>,
+
+    /// Time source used for sleeping.
+    source: S,
+}
+
+/// Randomization of backoff delays
+///
+/// Given the delay `d` an unrandomized backoff would use, a randomized delay is picked as
+/// follows.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Jitter {
+    /// No randomization, always `d`.
+    None,
+    /// Anything between zero and `d`.
+    Full,
+    /// Anything between `d / 2` and `d`.
+    Equal,
+    /// Anything between the initial delay and three times the previous delay, regardless of `d`.
+    /// Still limited by the maximum delay.
+    Decorrelated,
+}
+
+impl Default for Jitter {
+    #[inline]
+    fn default() -> Self {
+        Jitter::None
+    }
+}
+
+impl Backoff {
+    /// Creates a new exponential backoff, starting with a delay of `initial`
+    #[inline]
+    pub fn new(initial: time::Duration) -> Backoff {
+        Backoff::new_with_source(initial, SystemClock)
+    }
+}
+
+impl<S> Backoff<S> {
+    /// Creates a new exponential backoff using a custom time source
+    ///
+    /// By default, the delay doubles after every iteration, without limits or jitter.
+    #[inline]
+    pub fn new_with_source(initial: time::Duration, source: S) -> Backoff<S> {
+        Backoff {
+            initial,
+            factor: 2.0,
+            max_delay: None,
+            max_elapsed_time: None,
+            jitter: Jitter::default(),
+            rng: Rng::from_entropy(),
+            retries: 0,
+            last_delay: None,
+            started_at: None,
+            source,
+        }
+    }
+
+    /// Sets the factor the delay grows by after every iteration
+    ///
+    /// Panics if `factor` is less than 1 or not finite.
+    #[inline]
+    pub fn with_factor(mut self, factor: f64) -> Self {
+        assert!(
+            factor >= 1.0 && factor.is_finite(),
+            "factor must be at least 1 and finite"
+        );
+
+        self.factor = factor;
+        self
+    }
+
+    /// Limits a single delay to `max_delay`
+    #[inline]
+    pub fn with_max_delay(mut self, max_delay: time::Duration) -> Self {
+        self.max_delay = Some(max_delay);
+        self
+    }
+
+    /// Ends iteration instead of delaying past `max_elapsed_time` after the first iteration
+    #[inline]
+    pub fn with_max_elapsed_time(mut self, max_elapsed_time: time::Duration) -> Self {
+        self.max_elapsed_time = Some(max_elapsed_time);
+        self
+    }
+
+    /// Sets the randomization applied to delays
+    #[inline]
+    pub fn with_jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Seeds the random number generator used for jitter, making delays reproducible
+    #[inline]
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    /// Number of delays inserted so far
+    #[inline]
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Most recent delay, `None` if there has been none yet
+    #[inline]
+    pub fn last_delay(&self) -> Option<time::Duration> {
+        self.last_delay
+    }
+
+    /// Starts over, as if the backoff was newly created
+    ///
+    /// Useful to reuse a backoff after an operation succeeded.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.retries = 0;
+        self.last_delay = None;
+        self.started_at = None;
+    }
+
+    /// Calculates the delay before the next retry.
+    fn next_delay(&mut self) -> time::Duration {
+        let cap = self.max_delay.unwrap_or(time::Duration::MAX);
+        let zero = time::Duration::from_secs(0);
+
+        // once the factor overflows to infinity, a zero initial delay would
+        // turn into NaN
+        let base = if self.initial == zero {
+            zero
+        } else {
+            duration_from_secs(
+                self.initial.as_secs_f64()
+                    * self.factor.powi(self.retries.min(i32::MAX as u32) as i32),
+            )
+            .min(cap)
+        };
+
+        match self.jitter {
+            Jitter::None => base,
+            Jitter::Full => self.rng.duration_between(zero, base),
+            Jitter::Equal => base / 2 + self.rng.duration_between(zero, base - base / 2),
+            Jitter::Decorrelated => {
+                let upper = match self.last_delay {
+                    Some(last) => last.checked_mul(3).unwrap_or(time::Duration::MAX),
+                    None => self.initial,
+                };
+
+                self.rng
+                    .duration_between(self.initial.min(upper), upper)
+                    .min(cap)
+            }
+        }
+    }
+}
+
+impl<S> iter::Iterator for Backoff<S>
+where
+    S: Sleeper,
+{
+    type Item = ();
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let started_at = match self.started_at {
+            Some(started_at) => started_at,
+            None => {
+                self.started_at = Some(self.source.now());
+                return Some(());
+            }
+        };
+
+        let delay = self.next_delay();
+
+        if let Some(max_elapsed_time) = self.max_elapsed_time {
+            let elapsed = self.source.now().saturating_duration_since(started_at);
+
+            if elapsed.saturating_add(delay) > max_elapsed_time {
+                return None;
+            }
+        }
+
+        self.source.sleep(delay);
+        self.retries = self.retries.saturating_add(1);
+        self.last_delay = Some(delay);
+
+        Some(())
+    }
+}
+
+/// Converts seconds into a duration, saturating at the maximum duration.
+#[inline]
+fn duration_from_secs(secs: f64) -> time::Duration {
+    if secs >= u64::MAX as f64 {
+        time::Duration::MAX
+    } else {
+        time::Duration::from_secs_f64(secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time_source::MockClock;
+
+    #[test]
+    fn delays_after_first_iteration() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let mut delay = Delay::new_with_source(time::Duration::from_secs(1), mock.clone());
+
+        delay.next();
+        assert_eq!(mock.now(), start);
+
+        delay.next();
+        delay.next();
+        assert_eq!(mock.now() - start, time::Duration::from_secs(2));
+    }
+
+    #[test]
+    fn delayed_delays_first() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let mut delay = Delay::delayed_with_source(time::Duration::from_secs(1), mock.clone());
+
+        delay.next();
+        assert_eq!(mock.now() - start, time::Duration::from_secs(1));
+    }
+
+    /// Collects the delays inserted by `backoff`.
+    fn delays(backoff: Backoff<MockClock>, mock: &MockClock) -> Vec<time::Duration> {
+        let mut last = mock.now();
+
+        backoff
+            .map(|_| {
+                let now = mock.now();
+                let delay = now - last;
+                last = now;
+                delay
+            })
+            .skip(1)
+            .take(20)
+            .collect()
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        let mock = MockClock::new();
+        let backoff = Backoff::new_with_source(time::Duration::from_millis(100), mock.clone())
+            .with_factor(3.0)
+            .with_max_delay(time::Duration::from_secs(2))
+            .with_max_elapsed_time(time::Duration::from_secs(6));
+
+        assert_eq!(
+            delays(backoff, &mock),
+            vec![
+                time::Duration::from_millis(100),
+                time::Duration::from_millis(300),
+                time::Duration::from_millis(900),
+                time::Duration::from_secs(2),
+                time::Duration::from_secs(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn jitter_stays_in_bounds() {
+        let initial = time::Duration::from_millis(100);
+        let cap = time::Duration::from_secs(1);
+        let mock = MockClock::new();
+        let backoff = |jitter| {
+            Backoff::new_with_source(initial, mock.clone())
+                .with_max_delay(cap)
+                .with_jitter(jitter)
+                .with_seed(42)
+        };
+
+        let full = delays(backoff(Jitter::Full), &mock);
+        let equal = delays(backoff(Jitter::Equal), &mock);
+        let decorrelated = delays(backoff(Jitter::Decorrelated), &mock);
+
+        for (n, (full, equal)) in full.iter().zip(equal.iter()).enumerate() {
+            let base = (initial * 2u32.pow(n.min(10) as u32)).min(cap);
+
+            assert!(*full <= base);
+            assert!(*equal >= base / 2 && *equal <= base);
+        }
+
+        assert_eq!(decorrelated[0], initial);
+        for pair in decorrelated.windows(2) {
+            assert!(pair[1] >= initial && pair[1] <= (pair[0] * 3).min(cap));
+        }
+
+        // seeded, so reproducible
+        assert_eq!(full, delays(backoff(Jitter::Full), &mock));
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let mut backoff = Backoff::new_with_source(time::Duration::from_secs(1), mock.clone());
+
+        backoff.next();
+        backoff.next();
+        backoff.next();
+        assert_eq!(backoff.retries(), 2);
+        assert_eq!(backoff.last_delay(), Some(time::Duration::from_secs(2)));
+
+        backoff.reset();
+        backoff.next();
+        backoff.next();
+        assert_eq!(mock.now() - start, time::Duration::from_secs(4));
+    }
+
+    #[test]
+    fn zero_initial_delay_survives_overflow() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let backoff = Backoff::new_with_source(time::Duration::from_secs(0), mock.clone());
+
+        // the factor overflows f64 after about 1024 retries
+        assert_eq!(backoff.take(2000).count(), 2000);
+        assert_eq!(mock.now(), start);
+    }
+}
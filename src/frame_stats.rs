@@ -0,0 +1,338 @@
+//! Frame timing statistics
+//!
+//! Collects frame times from clock ticks, see `FrameStats`.
+
+use crate::time_source::TimeSource;
+use std::time;
+
+/// Default number of frames statistics are calculated over.
+const DEFAULT_CAPACITY: usize = 120;
+
+/// Rolling statistics of frame times
+///
+/// Fed with the tick numbers returned by a clock iterator and the actual
+/// time each frame happened at, keeps the times between the most recent
+/// frames in a fixed-size ring buffer. Recording a frame is cheap and never
+/// allocates; statistics are calculated over the buffered frames when
+/// requested.
+///
+/// Frames should be recorded at the actual current time, not at the instant
+/// returned by the clock iterator: that is the instant the tick was
+/// scheduled at, so oversleeping and frames running late would not show up.
+///
+/// Frames are missed when tick numbers are skipped, see
+/// `MissedTickBehavior::Skip`.
+///
+/// ```
+/// use std::time;
+/// use ticktock::{Clock, FrameStats};
+/// use ticktock::time_source::MockClock;
+///
+/// let mock = MockClock::new();
+/// let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone());
+/// let mut stats = FrameStats::new(60);
+///
+/// for (tick, _) in clock.iter().take(10) {
+///     // the 5th frame takes 4 ms to render
+///     if tick == 5 {
+///         mock.advance(time::Duration::from_millis(4));
+///     }
+///
+///     stats.record_now(tick, clock.source());
+/// }
+///
+/// assert_eq!(stats.max_frame_time(), Some(time::Duration::from_millis(14)));
+/// assert_eq!(stats.min_frame_time(), Some(time::Duration::from_millis(6)));
+/// assert_eq!(stats.missed_frames(), 0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct FrameStats {
+    /// Times between the most recent frames
+    frame_times: Vec<time::Duration>,
+    /// The same frame times, kept sorted for percentiles
+    sorted: Vec<time::Duration>,
+    /// Maximum number of frame times kept
+    capacity: usize,
+    /// Index the next frame time is written to, once the buffer is full
+    next: usize,
+    /// Tick number and instant of the last frame
+    last: Option<(u128, time::Instant)>,
+    /// Frames recorded since creation or reset
+    frames: u64,
+    /// Frames missed since creation or reset
+    missed: u128,
+}
+
+impl FrameStats {
+    /// Creates new statistics over the last `capacity` frames
+    ///
+    /// Panics if `capacity` is zero.
+    #[inline]
+    pub fn new(capacity: usize) -> FrameStats {
+        assert!(capacity > 0, "capacity must not be zero");
+
+        FrameStats {
+            frame_times: Vec::with_capacity(capacity),
+            sorted: Vec::with_capacity(capacity),
+            capacity,
+            next: 0,
+            last: None,
+            frames: 0,
+            missed: 0,
+        }
+    }
+
+    /// Get the maximum number of frames statistics are calculated over
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Records a frame at tick number `tick_num`, happening at `now`
+    ///
+    /// `now` should be the actual time, e.g. `Instant::now()`, not the
+    /// scheduled instant of the tick. The first frame recorded only serves as
+    /// a reference for the following ones.
+    pub fn record(&mut self, tick_num: u128, now: time::Instant) {
+        if let Some((last_tick, last_instant)) = self.last {
+            let frame_time = now.saturating_duration_since(last_instant);
+
+            if self.frame_times.len() < self.capacity {
+                self.frame_times.push(frame_time);
+            } else {
+                let oldest = std::mem::replace(&mut self.frame_times[self.next], frame_time);
+                self.next = (self.next + 1) % self.capacity;
+
+                if let Ok(index) = self.sorted.binary_search(&oldest) {
+                    self.sorted.remove(index);
+                }
+            }
+
+            let index = self.sorted.partition_point(|&t| t < frame_time);
+            self.sorted.insert(index, frame_time);
+
+            self.missed += tick_num.saturating_sub(last_tick + 1);
+        }
+
+        self.frames += 1;
+        self.last = Some((tick_num, now));
+    }
+
+    /// Records a frame at tick number `tick_num`, reading the current time
+    /// from a time source
+    ///
+    /// See `record` for details.
+    #[inline]
+    pub fn record_now<S: TimeSource>(&mut self, tick_num: u128, source: &S) {
+        self.record(tick_num, source.now());
+    }
+
+    /// Discards all recorded frames
+    #[inline]
+    pub fn reset(&mut self) {
+        self.frame_times.clear();
+        self.sorted.clear();
+        self.next = 0;
+        self.last = None;
+        self.frames = 0;
+        self.missed = 0;
+    }
+
+    /// Total number of frames recorded
+    #[inline]
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Total number of frames missed, i.e. tick numbers skipped between
+    /// recorded frames
+    #[inline]
+    pub fn missed_frames(&self) -> u128 {
+        self.missed
+    }
+
+    /// Average frames per second over the buffered frames
+    ///
+    /// Returns 0 if fewer than two frames have been recorded.
+    #[inline]
+    pub fn fps(&self) -> f64 {
+        match self.mean_frame_time() {
+            Some(mean) if mean > time::Duration::from_secs(0) => 1.0 / mean.as_secs_f64(),
+            _ => 0.0,
+        }
+    }
+
+    /// Average time between the buffered frames
+    #[inline]
+    pub fn mean_frame_time(&self) -> Option<time::Duration> {
+        if self.frame_times.is_empty() {
+            return None;
+        }
+
+        let total: time::Duration = self.frame_times.iter().sum();
+        let mean = total.as_nanos() / self.frame_times.len() as u128;
+        Some(time::Duration::from_nanos(mean as u64))
+    }
+
+    /// Shortest time between the buffered frames
+    #[inline]
+    pub fn min_frame_time(&self) -> Option<time::Duration> {
+        self.sorted.first().copied()
+    }
+
+    /// Longest time between the buffered frames
+    #[inline]
+    pub fn max_frame_time(&self) -> Option<time::Duration> {
+        self.sorted.last().copied()
+    }
+
+    /// Frame time percentile over the buffered frames
+    ///
+    /// Uses the nearest-rank method, i.e. `p` percent of the buffered frame
+    /// times are less than or equal to the result. Frame times are kept
+    /// sorted while recording, so this is a simple lookup.
+    ///
+    /// Panics if `p` is not within `[0, 100]`.
+    #[inline]
+    pub fn percentile(&self, p: f64) -> Option<time::Duration> {
+        assert!(
+            (0.0..=100.0).contains(&p),
+            "percentile must be within [0, 100]"
+        );
+
+        if self.sorted.is_empty() {
+            return None;
+        }
+
+        let rank = (p / 100.0 * self.sorted.len() as f64).ceil() as usize;
+        Some(self.sorted[rank.max(1) - 1])
+    }
+
+    /// Median frame time, see `percentile`
+    #[inline]
+    pub fn p50(&self) -> Option<time::Duration> {
+        self.percentile(50.0)
+    }
+
+    /// 95th percentile frame time, see `percentile`
+    #[inline]
+    pub fn p95(&self) -> Option<time::Duration> {
+        self.percentile(95.0)
+    }
+
+    /// 99th percentile frame time, see `percentile`
+    #[inline]
+    pub fn p99(&self) -> Option<time::Duration> {
+        self.percentile(99.0)
+    }
+
+    /// Frame time jitter, as the standard deviation of the buffered frame
+    /// times
+    pub fn jitter(&self) -> Option<time::Duration> {
+        let mean = self.mean_frame_time()?.as_secs_f64();
+        let variance = self
+            .frame_times
+            .iter()
+            .map(|frame_time| (frame_time.as_secs_f64() - mean).powi(2))
+            .sum::<f64>()
+            / self.frame_times.len() as f64;
+
+        Some(time::Duration::from_secs_f64(variance.sqrt()))
+    }
+}
+
+impl Default for FrameStats {
+    #[inline]
+    fn default() -> FrameStats {
+        FrameStats::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time_source::MockClock;
+    use crate::Clock;
+
+    #[test]
+    fn percentiles_and_jitter() {
+        let start = time::Instant::now();
+        let mut stats = FrameStats::new(100);
+        let mut now = start;
+
+        stats.record(0, now);
+        assert_eq!(stats.p50(), None);
+        assert_eq!(stats.fps(), 0.0);
+
+        // frame times of 1..=100 ms
+        for n in 1..=100 {
+            now += time::Duration::from_millis(n);
+            stats.record(n as u128, now);
+        }
+
+        let ms = time::Duration::from_millis;
+        assert_eq!(stats.frames(), 101);
+        assert_eq!(stats.min_frame_time(), Some(ms(1)));
+        assert_eq!(stats.max_frame_time(), Some(ms(100)));
+        assert_eq!(stats.percentile(0.0), Some(ms(1)));
+        assert_eq!(stats.p50(), Some(ms(50)));
+        assert_eq!(stats.p95(), Some(ms(95)));
+        assert_eq!(stats.p99(), Some(ms(99)));
+        assert_eq!(
+            stats.mean_frame_time(),
+            Some(time::Duration::from_micros(50500))
+        );
+
+        // standard deviation of 1..=100 is sqrt(833.25)
+        let jitter = stats.jitter().unwrap().as_secs_f64() * 1000.0;
+        assert!((jitter - 833.25f64.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ring_buffer_keeps_recent_frames() {
+        let start = time::Instant::now();
+        let ms = |n| start + time::Duration::from_millis(n);
+        let mut stats = FrameStats::new(3);
+
+        stats.record(1, ms(0));
+        stats.record(2, ms(50));
+        for n in 3..=5 {
+            stats.record(n, ms(50 + 10 * (n as u64 - 2)));
+        }
+        assert_eq!(
+            stats.max_frame_time(),
+            Some(time::Duration::from_millis(10))
+        );
+        assert_eq!(stats.fps(), 100.0);
+        assert_eq!(stats.p99(), Some(time::Duration::from_millis(10)));
+
+        // ticks 6 to 8 are missed
+        stats.record(9, ms(90));
+        assert_eq!(stats.missed_frames(), 3);
+        assert_eq!(stats.capacity(), 3);
+
+        stats.reset();
+        assert_eq!(stats.frames(), 0);
+        assert_eq!(stats.mean_frame_time(), None);
+    }
+
+    #[test]
+    fn measures_actual_frame_times() {
+        let mock = MockClock::new();
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), mock.clone());
+        let mut stats = FrameStats::new(10);
+
+        // frames finish 1, 5 and 2 ms after their tick
+        for ((tick, _), work) in clock.iter().zip(vec![1, 5, 2]) {
+            mock.advance(time::Duration::from_millis(work));
+            stats.record_now(tick, &mock);
+        }
+
+        let ms = time::Duration::from_millis;
+        assert_eq!(stats.frames(), 3);
+        assert_eq!(stats.max_frame_time(), Some(ms(14)));
+        assert_eq!(stats.min_frame_time(), Some(ms(7)));
+        assert!(stats.jitter().unwrap() > ms(3));
+        assert_eq!(stats.missed_frames(), 0);
+    }
+}
@@ -14,15 +14,18 @@
 //!
 //! ```ignore
 //! use std::time;
-//! use ticktock::{Clock, Timer};
+//! use ticktock::{Clock, FrameStats, Timer};
 //!
 //! let now = time::Instant::now();
 //!
 //! // initialize game
 //! // ...
 //!
+//! // keep timing statistics over the last 120 frames
+//! let mut frame_stats = FrameStats::new(120);
+//!
 //! // show some fps measurements every 5 seconds
-//! let mut fps_counter = Timer::apply(|delta_t, prev_tick| (delta_t, *prev_tick), 0)
+//! let mut fps_counter = Timer::apply(|_, _| (), ())
 //!     .every(time::Duration::from_secs(5))
 //!     .start(now);
 //!
@@ -33,12 +36,15 @@
 //!     // update, render, etc
 //!     // ...
 //!
-//!     // update or display fps count
-//!     if let Some((delta_t, prev_tick)) = fps_counter.update(now) {
-//!         fps_counter.set_value(tick);
-//!
-//!         let fps = (tick - prev_tick) as f64 / delta_t.as_secs_f64();
-//!         println!("FPS: {}", fps);
+//!     // record the actual frame time, not the scheduled tick, display
+//!     // measurements
+//!     frame_stats.record(tick, time::Instant::now());
+//!     if fps_counter.update(now).is_some() {
+//!         println!(
+//!             "FPS: {}, p99 frame time: {:?}",
+//!             frame_stats.fps(),
+//!             frame_stats.p99()
+//!         );
 //!     }
 //!     break; // ignore, for doctests
 //! }
@@ -46,10 +52,21 @@
 
 pub mod clock;
 pub mod delay;
+pub mod frame_stats;
+#[cfg(target_os = "linux")]
+pub mod linux;
+pub mod retry;
+mod rng;
+pub mod schedule;
+pub mod sleep;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub mod stream;
 pub mod throttled_io;
+pub mod time_source;
 pub mod timer;
 
-pub use crate::clock::Clock;
+pub use crate::clock::{Clock, MissedTickBehavior};
+pub use crate::frame_stats::FrameStats;
 pub use crate::timer::Timer;
 
 /// Iterator attempt
@@ -95,11 +112,9 @@ pub use crate::timer::Timer;
 ///                                                       .attempt()
 ///                                                       .unwrap();
 /// ```
-
 // note: this could probably be expressed more cleanly by using associated types
 // (i.e. `type Outcome = ...`), but a bug in the rust compiler at the time of this writing
 // did not allow for it https://github.com/rust-lang/rust/issues/20400
-
 pub trait Attempt<O> {
     /// Consumes until the successful outcome is encountered. In case of failure, returns the last
     /// unsuccessful outcome.
@@ -0,0 +1,225 @@
+//! Linux specific time sources
+//!
+//! `Clock` computes the absolute instant of the next tick, which a regular `Sleeper` turns back
+//! into a relative duration to sleep for. Any time passing between reading the current time and
+//! actually going to sleep is added to the sleep. `NanosleepClock` avoids this by sleeping until
+//! the absolute deadline using `clock_nanosleep` with `TIMER_ABSTIME`.
+//!
+//! ```
+//! use std::time;
+//! use ticktock::linux::NanosleepClock;
+//! use ticktock::Clock;
+//!
+//! let clock = Clock::new_with_source(time::Duration::from_millis(5), NanosleepClock::monotonic());
+//!
+//! for (_, tick) in clock.iter().take(3) {
+//!     assert!(time::Instant::now() >= tick);
+//! }
+//! ```
+//!
+//! For `epoll` based event loops, `TimerFdClock` signals ticks through a file descriptor instead of
+//! sleeping.
+
+mod timerfd;
+
+use crate::time_source::{Sleeper, TimeSource};
+use std::{io, mem, ptr, time};
+
+pub use self::timerfd::TimerFdClock;
+
+/// Kernel clock to read and sleep on
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ClockId {
+    /// `CLOCK_MONOTONIC`, does not advance while the system is suspended.
+    ///
+    /// This is the default and the clock `Instant::now()` is based on.
+    Monotonic,
+    /// `CLOCK_BOOTTIME`, includes time the system spent suspended.
+    ///
+    /// Instants returned by a time source on this clock run ahead of
+    /// `Instant::now()` after the system has been suspended, so the two must
+    /// not be mixed.
+    Boottime,
+}
+
+impl Default for ClockId {
+    #[inline]
+    fn default() -> Self {
+        ClockId::Monotonic
+    }
+}
+
+impl ClockId {
+    /// Returns the raw clock id
+    #[inline]
+    fn raw(self) -> libc::clockid_t {
+        match self {
+            ClockId::Monotonic => libc::CLOCK_MONOTONIC,
+            ClockId::Boottime => libc::CLOCK_BOOTTIME,
+        }
+    }
+}
+
+/// A time source sleeping until absolute deadlines
+///
+/// Reads the time and sleeps using `clock_gettime` and `clock_nanosleep` on
+/// the selected kernel clock. Instants are mapped to the kernel clock
+/// through a reference point taken on creation.
+#[derive(Clone, Copy, Debug)]
+pub struct NanosleepClock {
+    /// Kernel clock used
+    clock_id: ClockId,
+    /// Instant of the reference point
+    anchor: time::Instant,
+    /// Kernel clock time of the reference point
+    anchor_time: time::Duration,
+}
+
+impl NanosleepClock {
+    /// Creates a new time source on the kernel clock `clock_id`
+    #[inline]
+    pub fn new(clock_id: ClockId) -> NanosleepClock {
+        NanosleepClock {
+            clock_id,
+            anchor: time::Instant::now(),
+            anchor_time: clock_gettime(clock_id),
+        }
+    }
+
+    /// Creates a new time source on `CLOCK_MONOTONIC`
+    #[inline]
+    pub fn monotonic() -> NanosleepClock {
+        NanosleepClock::new(ClockId::Monotonic)
+    }
+
+    /// Creates a new time source on `CLOCK_BOOTTIME`
+    #[inline]
+    pub fn boottime() -> NanosleepClock {
+        NanosleepClock::new(ClockId::Boottime)
+    }
+
+    /// Get the kernel clock used
+    #[inline]
+    pub fn clock_id(&self) -> ClockId {
+        self.clock_id
+    }
+}
+
+impl Default for NanosleepClock {
+    #[inline]
+    fn default() -> Self {
+        NanosleepClock::monotonic()
+    }
+}
+
+impl TimeSource for NanosleepClock {
+    #[inline]
+    fn now(&self) -> time::Instant {
+        let now = clock_gettime(self.clock_id);
+
+        if now >= self.anchor_time {
+            self.anchor + (now - self.anchor_time)
+        } else {
+            self.anchor - (self.anchor_time - now)
+        }
+    }
+}
+
+impl Sleeper for NanosleepClock {
+    #[inline]
+    fn sleep(&self, duration: time::Duration) {
+        self.sleep_until(self.now() + duration)
+    }
+
+    fn sleep_until(&self, deadline: time::Instant) {
+        // deadlines before the reference point have passed already
+        let deadline = match deadline.checked_duration_since(self.anchor) {
+            Some(offset) => to_timespec(self.anchor_time + offset),
+            None => return,
+        };
+
+        loop {
+            // returns the error number instead of setting errno
+            //
+            // SAFETY: `deadline` is a valid `timespec` on the stack, outliving
+            // the call. The remaining time is only written for relative
+            // sleeps, so passing null is allowed with `TIMER_ABSTIME`. As the
+            // deadline is absolute, retrying after `EINTR` neither loses nor
+            // adds time.
+            let rv = unsafe {
+                libc::clock_nanosleep(
+                    self.clock_id.raw(),
+                    libc::TIMER_ABSTIME,
+                    &deadline,
+                    ptr::null_mut(),
+                )
+            };
+
+            match rv {
+                0 => return,
+                libc::EINTR => continue,
+                errno => panic!(
+                    "clock_nanosleep failed: {}",
+                    io::Error::from_raw_os_error(errno)
+                ),
+            }
+        }
+    }
+}
+
+/// Reads the current time of a kernel clock
+#[inline]
+fn clock_gettime(clock_id: ClockId) -> time::Duration {
+    // SAFETY: `timespec` is plain old data, all zeroes is a valid value.
+    let mut ts: libc::timespec = unsafe { mem::zeroed() };
+
+    // SAFETY: `ts` is a valid, writable `timespec` for the duration of the
+    // call. `clock_gettime` does not sleep, so it cannot fail with `EINTR`.
+    if unsafe { libc::clock_gettime(clock_id.raw(), &mut ts) } != 0 {
+        panic!("clock_gettime failed: {}", io::Error::last_os_error());
+    }
+
+    time::Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+/// Converts a kernel clock time to a `timespec`
+#[inline]
+fn to_timespec(duration: time::Duration) -> libc::timespec {
+    // SAFETY: `timespec` is plain old data, all zeroes is a valid value.
+    // Zeroing also covers padding fields on some targets.
+    let mut ts: libc::timespec = unsafe { mem::zeroed() };
+    ts.tv_sec = duration.as_secs() as libc::time_t;
+    ts.tv_nsec = duration.subsec_nanos() as _;
+    ts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleeps_until_deadline() {
+        for &clock_id in &[ClockId::Monotonic, ClockId::Boottime] {
+            let source = NanosleepClock::new(clock_id);
+            let deadline = source.now() + time::Duration::from_millis(5);
+
+            source.sleep_until(deadline);
+            assert!(source.now() >= deadline);
+
+            // returns immediately
+            source.sleep_until(deadline - time::Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn monotonic_matches_instant() {
+        let source = NanosleepClock::monotonic();
+        let before = time::Instant::now();
+        let now = source.now();
+        let after = time::Instant::now();
+
+        // allow for the error of the reference point
+        let slack = time::Duration::from_millis(1);
+        assert!(now + slack >= before && now <= after + slack);
+    }
+}
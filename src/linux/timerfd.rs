@@ -0,0 +1,293 @@
+//! Clocks backed by timer file descriptors.
+
+use super::{clock_gettime, to_timespec, ClockId};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::{io, mem, ptr, time};
+
+/// A clock ticking through a `timerfd`
+///
+/// Instead of sleeping, the kernel signals ticks by making the file
+/// descriptor readable, so the clock can be waited on by `epoll` based event
+/// loops alongside other file descriptors. With the `mio` feature enabled, it
+/// can be registered with a `mio::Poll` directly.
+///
+/// Like a `Clock`, the first tick happens one tick length after creation.
+/// Every read returns the number of ticks that passed since the last read,
+/// ticks that passed unnoticed are counted as missed, the same way
+/// `MissedTickBehavior::Skip` does.
+///
+/// The file descriptor is non-blocking, `try_tick` never waits.
+///
+/// ```
+/// use std::time;
+/// use ticktock::linux::TimerFdClock;
+///
+/// let mut clock = TimerFdClock::new(time::Duration::from_millis(5)).unwrap();
+///
+/// // nothing to read right after creation
+/// assert_eq!(clock.try_tick().unwrap(), None);
+///
+/// assert_eq!(clock.wait_until_tick().unwrap(), 1);
+/// ```
+#[derive(Debug)]
+pub struct TimerFdClock {
+    /// Timer file descriptor, closed on drop
+    fd: OwnedFd,
+    /// Kernel clock the timer runs on
+    clock_id: ClockId,
+    /// Tick length
+    tick_len: time::Duration,
+    /// Kernel clock time the clock was started at
+    started_at: time::Duration,
+    /// Number of the last tick read
+    last_tick: u128,
+    /// Ticks missed before the last tick read
+    missed: u128,
+    /// Ticks missed since creation
+    total_missed: u128,
+}
+
+impl TimerFdClock {
+    /// Creates a new clock on `CLOCK_MONOTONIC`, starting now
+    ///
+    /// Panics if `tick_len` is zero.
+    #[inline]
+    pub fn new(tick_len: time::Duration) -> io::Result<TimerFdClock> {
+        TimerFdClock::new_with_clock_id(tick_len, ClockId::Monotonic)
+    }
+
+    /// Creates a new clock on the kernel clock `clock_id`, starting now
+    ///
+    /// Using `ClockId::Boottime` keeps the clock ticking while the system is
+    /// suspended, the ticks passed are reported as missed after resuming.
+    ///
+    /// Panics if `tick_len` is zero.
+    pub fn new_with_clock_id(
+        tick_len: time::Duration,
+        clock_id: ClockId,
+    ) -> io::Result<TimerFdClock> {
+        assert!(
+            tick_len > time::Duration::from_secs(0),
+            "tick length must not be zero"
+        );
+
+        // SAFETY: takes no pointers, a negative result is handled below.
+        let raw =
+            unsafe { libc::timerfd_create(clock_id.raw(), libc::TFD_NONBLOCK | libc::TFD_CLOEXEC) };
+        if raw < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `raw` is a freshly created, open file descriptor owned by
+        // nobody else. `OwnedFd` takes over ownership and closes it exactly
+        // once on drop, including the early returns below.
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+
+        let started_at = clock_gettime(clock_id);
+        // SAFETY: `itimerspec` is plain old data, all zeroes is a valid value.
+        let mut spec: libc::itimerspec = unsafe { mem::zeroed() };
+        spec.it_interval = to_timespec(tick_len);
+        spec.it_value = to_timespec(started_at + tick_len);
+
+        // SAFETY: `fd` is open for the whole call. `spec` is a valid
+        // `itimerspec` on the stack, the old value is not requested, so null
+        // is allowed.
+        let rv = unsafe {
+            libc::timerfd_settime(
+                fd.as_raw_fd(),
+                libc::TFD_TIMER_ABSTIME,
+                &spec,
+                ptr::null_mut(),
+            )
+        };
+        if rv != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(TimerFdClock {
+            fd,
+            clock_id,
+            tick_len,
+            started_at,
+            last_tick: 0,
+            missed: 0,
+            total_missed: 0,
+        })
+    }
+
+    /// Get the tick length
+    #[inline]
+    pub fn tick_len(&self) -> time::Duration {
+        self.tick_len
+    }
+
+    /// Get the kernel clock the timer runs on
+    #[inline]
+    pub fn clock_id(&self) -> ClockId {
+        self.clock_id
+    }
+
+    /// Returns the time passed since the clock was started
+    #[inline]
+    pub fn elapsed(&self) -> time::Duration {
+        clock_gettime(self.clock_id).saturating_sub(self.started_at)
+    }
+
+    /// Number of ticks missed before the most recently read tick
+    #[inline]
+    pub fn missed_ticks(&self) -> u128 {
+        self.missed
+    }
+
+    /// Total number of ticks missed since the clock was created
+    #[inline]
+    pub fn total_missed_ticks(&self) -> u128 {
+        self.total_missed
+    }
+
+    /// Reads the ticks that passed since the last read, without waiting
+    ///
+    /// Returns the number of the most recent tick, or `None` if no tick
+    /// passed. Reading consumes all pending ticks, so when registered with an
+    /// edge-triggered event loop, a single call per readiness event suffices.
+    pub fn try_tick(&mut self) -> io::Result<Option<u128>> {
+        let mut expirations: u64 = 0;
+
+        loop {
+            // SAFETY: `self.fd` stays open while `self` is borrowed. The buffer
+            // is the 8 byte `u64` expiration counter a timerfd read requires,
+            // and its exact size is passed. Interrupted reads are retried.
+            let rv = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    &mut expirations as *mut u64 as *mut libc::c_void,
+                    mem::size_of::<u64>(),
+                )
+            };
+
+            if rv >= 0 {
+                break;
+            }
+
+            let err = io::Error::last_os_error();
+            match err.kind() {
+                io::ErrorKind::Interrupted => continue,
+                io::ErrorKind::WouldBlock => return Ok(None),
+                _ => return Err(err),
+            }
+        }
+
+        let expirations = u128::from(expirations);
+        self.last_tick += expirations;
+        self.missed = expirations.saturating_sub(1);
+        self.total_missed += self.missed;
+
+        Ok(Some(self.last_tick))
+    }
+
+    /// Waits for the next tick
+    ///
+    /// Blocks until at least one tick has passed, then returns the number of
+    /// the most recent tick, like `try_tick`.
+    pub fn wait_until_tick(&mut self) -> io::Result<u128> {
+        loop {
+            if let Some(tick) = self.try_tick()? {
+                return Ok(tick);
+            }
+
+            let mut pollfd = libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            // SAFETY: `pollfd` is a single valid entry on the stack, matching
+            // the count of 1, and refers to the open `self.fd`. Interrupted
+            // polls are retried by the surrounding loop.
+            if unsafe { libc::poll(&mut pollfd, 1, -1) } < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+impl AsRawFd for TimerFdClock {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+#[cfg(feature = "mio")]
+impl mio::event::Source for TimerFdClock {
+    #[inline]
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        mio::unix::SourceFd(&self.fd.as_raw_fd()).register(registry, token, interests)
+    }
+
+    #[inline]
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        mio::unix::SourceFd(&self.fd.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    #[inline]
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        mio::unix::SourceFd(&self.fd.as_raw_fd()).deregister(registry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn counts_missed_ticks() {
+        // a long tick, so nothing is pending even on a slow machine
+        let mut idle = TimerFdClock::new(time::Duration::from_secs(60)).unwrap();
+        assert_eq!(idle.try_tick().unwrap(), None);
+
+        let mut clock = TimerFdClock::new(time::Duration::from_millis(10)).unwrap();
+        assert_eq!(clock.wait_until_tick().unwrap(), 1);
+        assert_eq!(clock.missed_ticks(), 0);
+
+        thread::sleep(time::Duration::from_millis(45));
+        let tick = clock.try_tick().unwrap().unwrap();
+        assert!(tick >= 4);
+        assert_eq!(clock.missed_ticks(), tick - 2);
+        assert_eq!(clock.total_missed_ticks(), tick - 2);
+        assert!(clock.elapsed() >= time::Duration::from_millis(45));
+    }
+
+    #[cfg(feature = "mio")]
+    #[test]
+    fn wakes_mio_poll() {
+        let mut poll = mio::Poll::new().unwrap();
+        let mut events = mio::Events::with_capacity(4);
+        let mut clock = TimerFdClock::new(time::Duration::from_millis(10)).unwrap();
+
+        poll.registry()
+            .register(&mut clock, mio::Token(7), mio::Interest::READABLE)
+            .unwrap();
+
+        poll.poll(&mut events, Some(time::Duration::from_secs(1)))
+            .unwrap();
+        let event = events.iter().next().expect("timer did not fire");
+        assert_eq!(event.token(), mio::Token(7));
+        assert!(clock.try_tick().unwrap().is_some());
+
+        poll.registry().deregister(&mut clock).unwrap();
+    }
+}
@@ -0,0 +1,321 @@
+//! Retrying fallible operations
+//!
+//! Runs an operation until it succeeds, waiting between attempts according to a policy. Any
+//! iterator that waits between items can serve as a policy, e.g. a `Delay` or a `Backoff`, with
+//! every item permitting one attempt.
+//!
+//! Unlike `Attempt`, errors can be told apart: only errors classified as retryable lead to another
+//! attempt, anything else ends retrying immediately.
+//!
+//! ```rust
+//! use std::io;
+//! use std::net::TcpStream;
+//! use std::time::Duration;
+//! use ticktock::delay::Backoff;
+//! use ticktock::retry::retry;
+//!
+//! let policy = Backoff::new(Duration::from_millis(50)).take(3);
+//!
+//! let result = retry(policy, || TcpStream::connect("localhost:12348"))
+//!     .retry_if(|err: &io::Error| err.kind() == io::ErrorKind::ConnectionRefused)
+//!     .on_error(|attempt, err| eprintln!("attempt {} failed: {}", attempt, err))
+//!     .run();
+//!
+//! # // our test will fail, because there is noting listening at 12348
+//! # assert!(result.is_err());
+//! ```
+
+use std::marker::PhantomData;
+use std::{error, fmt};
+
+/// Creates a new retry of `op`, with attempts permitted by `policy`
+///
+/// See the module documentation for an example.
+#[inline]
+pub fn retry<P, F, T, E>(policy: P, op: F) -> Retry<P::IntoIter, F, E>
+where
+    P: IntoIterator,
+    F: FnMut() -> Result<T, E>,
+{
+    Retry {
+        policy: policy.into_iter(),
+        op,
+        retryable: always_retryable,
+        on_error: ignore_error,
+        collect_errors: false,
+        _error: PhantomData,
+    }
+}
+
+/// A retried operation
+///
+/// Created using `retry`, does nothing until `run` is called. The classifier
+/// `C` and the error callback `H` are stored as they are, so they may borrow
+/// local variables and the retry is `Send` if all of its parts are.
+pub struct Retry<I, F, E, C = fn(&E) -> bool, H = fn(u32, &E)> {
+    /// Iterator permitting attempts
+    policy: I,
+    /// Operation to attempt
+    op: F,
+    /// Classifies errors as retryable, every error is by default
+    retryable: C,
+    /// Called for every failed attempt
+    on_error: H,
+    /// Whether or not to keep all errors, instead of only the last
+    collect_errors: bool,
+    /// Error type, only used by the classifier and callback
+    _error: PhantomData<fn(&E)>,
+}
+
+/// Failure of a retried operation
+#[derive(Debug)]
+pub struct RetryError<E> {
+    /// Errors encountered, the last one always being the most recent
+    errors: Vec<E>,
+    /// Number of attempts made
+    attempts: u32,
+    /// Whether or not retrying stopped because of a non-retryable error
+    fatal: bool,
+}
+
+impl<I, F, T, E, C, H> Retry<I, F, E, C, H>
+where
+    I: Iterator,
+    F: FnMut() -> Result<T, E>,
+    C: FnMut(&E) -> bool,
+    H: FnMut(u32, &E),
+{
+    /// Only retry on errors for which `retryable` returns `true`
+    ///
+    /// Any other error is returned immediately.
+    #[inline]
+    pub fn retry_if<R>(self, retryable: R) -> Retry<I, F, E, R, H>
+    where
+        R: FnMut(&E) -> bool,
+    {
+        Retry {
+            policy: self.policy,
+            op: self.op,
+            retryable,
+            on_error: self.on_error,
+            collect_errors: self.collect_errors,
+            _error: PhantomData,
+        }
+    }
+
+    /// Calls `callback` with the attempt number, starting at 1, and the error of every failed
+    /// attempt
+    #[inline]
+    pub fn on_error<L>(self, callback: L) -> Retry<I, F, E, C, L>
+    where
+        L: FnMut(u32, &E),
+    {
+        Retry {
+            policy: self.policy,
+            op: self.op,
+            retryable: self.retryable,
+            on_error: callback,
+            collect_errors: self.collect_errors,
+            _error: PhantomData,
+        }
+    }
+
+    /// Keep the errors of all attempts, instead of only the last one
+    #[inline]
+    pub fn collect_errors(mut self) -> Self {
+        self.collect_errors = true;
+        self
+    }
+
+    /// Runs the operation until it succeeds, fails with a non-retryable error or the policy does
+    /// not permit any more attempts
+    pub fn run(mut self) -> Result<T, RetryError<E>> {
+        let mut errors = Vec::new();
+        let mut attempts = 0;
+
+        while self.policy.next().is_some() {
+            attempts += 1;
+
+            let err = match (self.op)() {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+
+            (self.on_error)(attempts, &err);
+            let fatal = !(self.retryable)(&err);
+
+            if !self.collect_errors {
+                errors.clear();
+            }
+            errors.push(err);
+
+            if fatal {
+                return Err(RetryError {
+                    errors,
+                    attempts,
+                    fatal: true,
+                });
+            }
+        }
+
+        Err(RetryError {
+            errors,
+            attempts,
+            fatal: false,
+        })
+    }
+}
+
+impl<I, F, E, C, H> fmt::Debug for Retry<I, F, E, C, H>
+where
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Retry")
+            .field("policy", &self.policy)
+            .field("collect_errors", &self.collect_errors)
+            .finish()
+    }
+}
+
+/// Default classifier, treating every error as retryable
+#[inline]
+fn always_retryable<E>(_: &E) -> bool {
+    true
+}
+
+/// Default error callback, doing nothing
+#[inline]
+fn ignore_error<E>(_: u32, _: &E) {}
+
+impl<E> RetryError<E> {
+    /// Number of attempts made
+    #[inline]
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Check whether retrying stopped because of a non-retryable error
+    ///
+    /// Otherwise, the policy did not permit any more attempts.
+    #[inline]
+    pub fn is_fatal(&self) -> bool {
+        self.fatal
+    }
+
+    /// Get the error of the last attempt
+    ///
+    /// Returns `None` if no attempt was made at all.
+    #[inline]
+    pub fn last_error(&self) -> Option<&E> {
+        self.errors.last()
+    }
+
+    /// Get all errors kept, oldest first
+    ///
+    /// Only contains the last error, unless `Retry::collect_errors` was used.
+    #[inline]
+    pub fn errors(&self) -> &[E] {
+        &self.errors
+    }
+
+    /// Returns the error of the last attempt
+    #[inline]
+    pub fn into_last_error(mut self) -> Option<E> {
+        self.errors.pop()
+    }
+
+    /// Returns all errors kept, oldest first
+    #[inline]
+    pub fn into_errors(self) -> Vec<E> {
+        self.errors
+    }
+}
+
+impl<E> fmt::Display for RetryError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.last_error() {
+            Some(err) if self.fatal => {
+                write!(f, "failed after {} attempts: {}", self.attempts, err)
+            }
+            Some(err) => write!(f, "gave up after {} attempts: {}", self.attempts, err),
+            None => f.write_str("no attempts made"),
+        }
+    }
+}
+
+impl<E> error::Error for RetryError<E>
+where
+    E: error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.last_error()
+            .map(|err| err as &(dyn error::Error + 'static))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delay::Delay;
+    use crate::time_source::MockClock;
+    use std::time;
+
+    #[test]
+    fn retries_until_success() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let policy = Delay::new_with_source(time::Duration::from_secs(1), mock.clone()).take(5);
+
+        let mut outcomes = vec![Err("a"), Err("b"), Ok(3)].into_iter();
+        let result = retry(policy, || outcomes.next().unwrap()).run();
+
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(mock.now() - start, time::Duration::from_secs(2));
+    }
+
+    #[test]
+    fn stops_on_fatal_errors() {
+        let mut seen = Vec::new();
+
+        let mut outcomes = vec![Err(1), Err(2), Err(-3), Ok(())].into_iter();
+        let err = retry(iter_forever(), || outcomes.next().unwrap())
+            .retry_if(|err: &i32| *err > 0)
+            .on_error(|attempt, err| seen.push((attempt, *err)))
+            .run()
+            .unwrap_err();
+
+        assert!(err.is_fatal());
+        assert_eq!(err.attempts(), 3);
+        assert_eq!(err.errors(), &[-3]);
+        assert_eq!(seen, vec![(1, 1), (2, 2), (3, -3)]);
+    }
+
+    #[test]
+    fn collects_errors_when_exhausted() {
+        let mut n = 0;
+        let err = retry(0..4, || -> Result<(), _> {
+            n += 1;
+            Err(n)
+        })
+        .collect_errors()
+        .run()
+        .unwrap_err();
+
+        assert!(!err.is_fatal());
+        assert_eq!(err.attempts(), 4);
+        assert_eq!(err.to_string(), "gave up after 4 attempts: 4");
+        assert_eq!(err.into_errors(), vec![1, 2, 3, 4]);
+
+        let err = retry(0..0, || Err::<(), _>(())).run().unwrap_err();
+        assert_eq!(err.attempts(), 0);
+        assert_eq!(err.last_error(), None);
+    }
+
+    fn iter_forever() -> impl Iterator<Item = ()> {
+        std::iter::repeat(())
+    }
+}
@@ -0,0 +1,111 @@
+//! Pseudo random numbers
+//!
+//! A tiny, seedable generator (SplitMix64) for simulated faults and jitter.
+//! Not suitable for anything requiring actual randomness.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time;
+
+/// A seedable pseudo random number generator.
+#[derive(Clone, Debug)]
+pub(crate) struct Rng {
+    /// Internal state.
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a new generator from a seed.
+    #[inline]
+    pub(crate) fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /// Creates a new generator with a seed that differs between calls and
+    /// processes.
+    #[inline]
+    pub(crate) fn from_entropy() -> Rng {
+        // hash maps are randomly keyed by the standard library
+        Rng::new(RandomState::new().build_hasher().finish())
+    }
+
+    /// Returns the next random number.
+    #[inline]
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random number in `[0, 1)`.
+    #[inline]
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        // use the upper 53 bits, as many as fit into the mantissa
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns `true` with a probability of `p`.
+    #[inline]
+    pub(crate) fn chance(&mut self, p: f64) -> bool {
+        p > 0.0 && self.next_f64() < p
+    }
+
+    /// Returns a random number in `[low, high]`.
+    #[inline]
+    pub(crate) fn between(&mut self, low: u64, high: u64) -> u64 {
+        debug_assert!(low <= high);
+
+        match (high - low).checked_add(1) {
+            Some(range) => low + self.next_u64() % range,
+            None => self.next_u64(),
+        }
+    }
+
+    /// Returns a random duration in `[low, high]`, with nanosecond resolution.
+    #[inline]
+    pub(crate) fn duration_between(
+        &mut self,
+        low: time::Duration,
+        high: time::Duration,
+    ) -> time::Duration {
+        let low_ns = low.as_nanos().min(u64::MAX as u128) as u64;
+        let high_ns = high.as_nanos().min(u64::MAX as u128) as u64;
+
+        time::Duration::from_nanos(self.between(low_ns, high_ns))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn ranges_are_respected() {
+        let mut rng = Rng::new(1);
+
+        for _ in 0..1000 {
+            let f = rng.next_f64();
+            assert!((0.0..1.0).contains(&f));
+
+            let n = rng.between(3, 5);
+            assert!((3..=5).contains(&n));
+        }
+
+        assert!(!rng.chance(0.0));
+        assert!(rng.chance(1.0));
+        assert_eq!(rng.between(7, 7), 7);
+    }
+}
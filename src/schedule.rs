@@ -0,0 +1,529 @@
+//! Schedules
+//!
+//! A `Schedule` determines when a timer fires, as an alternative to the fixed
+//! intervals set with `TimerBuilder::every`. Schedules work on monotonic
+//! `Instant`s, like the rest of the crate.
+//!
+//! Schedules following the wall clock, like "every day at 03:00 UTC", are
+//! described by a `Calendar` operating on `SystemTime`, e.g. a `Cron`
+//! expression, and turned into a `Schedule` by wrapping them in a `WallClock`:
+//!
+//! ```
+//! use std::time;
+//! use ticktock::schedule::{Cron, WallClock};
+//! use ticktock::Timer;
+//!
+//! let nightly = WallClock::new("0 3 * * *".parse::<Cron>().unwrap());
+//! let mut backup = Timer::apply(|_, runs| *runs += 1, 0)
+//!     .schedule(nightly)
+//!     .start(time::Instant::now());
+//!
+//! // in the main loop
+//! backup.update(time::Instant::now());
+//! ```
+
+use std::{error, fmt, str, time};
+
+/// A source of deadlines
+pub trait Schedule {
+    /// Returns the first deadline after `after`
+    ///
+    /// Returns `None` if the schedule will never fire again.
+    fn next_after(&mut self, after: time::Instant) -> Option<time::Instant>;
+}
+
+/// A schedule based on wall clock time
+pub trait Calendar {
+    /// Returns the first point in time after `after` that matches
+    ///
+    /// Returns `None` if no such point exists.
+    fn next_after(&self, after: time::SystemTime) -> Option<time::SystemTime>;
+}
+
+/// Adapts a `Calendar` to a `Schedule`
+///
+/// Deadlines are converted from wall clock time to `Instant`s whenever a new
+/// deadline is requested, using the current system time. Changes to the
+/// system time are therefore only picked up once the timer fired.
+#[derive(Clone, Debug)]
+pub struct WallClock<C> {
+    /// Underlying calendar.
+    calendar: C,
+    /// Last deadline returned, in both monotonic and wall clock time.
+    last: Option<(time::Instant, time::SystemTime)>,
+}
+
+impl<C> WallClock<C> {
+    /// Creates a new wall clock schedule
+    #[inline]
+    pub fn new(calendar: C) -> WallClock<C> {
+        WallClock {
+            calendar,
+            last: None,
+        }
+    }
+
+    /// Get the underlying calendar
+    #[inline]
+    pub fn calendar(&self) -> &C {
+        &self.calendar
+    }
+}
+
+impl<C> Schedule for WallClock<C>
+where
+    C: Calendar,
+{
+    fn next_after(&mut self, after: time::Instant) -> Option<time::Instant> {
+        let now = time::Instant::now();
+        let wall_now = time::SystemTime::now();
+
+        // measure from the previous deadline if possible, reading both clocks
+        // is not atomic and might map `after` to just before that deadline
+        let wall_after = match self.last {
+            Some((last, wall_last)) if after >= last => wall_last.checked_add(after - last)?,
+            _ if after >= now => wall_now.checked_add(after - now)?,
+            _ => wall_now.checked_sub(now - after)?,
+        };
+
+        let wall_next = self.calendar.next_after(wall_after)?;
+
+        let next = match wall_next.duration_since(wall_now) {
+            Ok(ahead) => now.checked_add(ahead)?,
+            Err(err) => now.checked_sub(err.duration())?,
+        };
+
+        self.last = Some((next, wall_next));
+        Some(next)
+    }
+}
+
+/// A cron expression
+///
+/// Consists of five fields separated by whitespace: minute (0-59), hour
+/// (0-23), day of month (1-31), month (1-12 or `JAN`-`DEC`) and day of week
+/// (0-7 or `SUN`-`SAT`, both 0 and 7 being sunday). Each field is either `*`
+/// or a comma separated list of values and ranges (`a-b`), each optionally
+/// followed by a step (`*/15`, `8-18/2`).
+///
+/// As with traditional cron, if both day of month and day of week are
+/// restricted, a day matches if either matches. A field starting with `*`,
+/// e.g. `*/2`, does not count as restricted.
+///
+/// The shortcuts `@yearly` (or `@annually`), `@monthly`, `@weekly`, `@daily`
+/// (or `@midnight`) and `@hourly` are supported as well. All times are UTC.
+///
+/// ```
+/// use std::time;
+/// use ticktock::schedule::{Calendar, Cron};
+///
+/// let cron: Cron = "30 3 * * MON-FRI".parse().unwrap();
+///
+/// // thursday, 1970-01-01 00:00 UTC
+/// let next = cron.next_after(time::UNIX_EPOCH).unwrap();
+/// assert_eq!(
+///     next.duration_since(time::UNIX_EPOCH).unwrap(),
+///     time::Duration::from_secs(3 * 3600 + 30 * 60)
+/// );
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cron {
+    /// Matching minutes, bit `n` set for minute `n`.
+    minutes: u64,
+    /// Matching hours.
+    hours: u64,
+    /// Matching days of month, starting at bit 1.
+    days: u64,
+    /// Matching months, starting at bit 1.
+    months: u64,
+    /// Matching days of week, bit 0 being sunday.
+    weekdays: u64,
+    /// Whether or not the day of month is restricted.
+    days_restricted: bool,
+    /// Whether or not the day of week is restricted.
+    weekdays_restricted: bool,
+}
+
+/// An error parsing a cron expression
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseCronError {
+    /// Description of the problem.
+    message: String,
+}
+
+impl Cron {
+    /// Parses a cron expression
+    ///
+    /// Equivalent to `str::parse`.
+    #[inline]
+    pub fn parse(expr: &str) -> Result<Cron, ParseCronError> {
+        expr.parse()
+    }
+
+    /// Creates a schedule firing every day at the given time
+    ///
+    /// Panics if `hour` or `minute` are out of range.
+    #[inline]
+    pub fn daily_at(hour: u32, minute: u32) -> Cron {
+        assert!(hour < 24, "hour out of range");
+        assert!(minute < 60, "minute out of range");
+
+        Cron {
+            minutes: 1 << minute,
+            hours: 1 << hour,
+            days: bits(1, 31),
+            months: bits(1, 12),
+            weekdays: bits(0, 6),
+            days_restricted: false,
+            weekdays_restricted: false,
+        }
+    }
+
+    /// Checks whether the day given as days since the unix epoch matches.
+    #[inline]
+    fn day_matches(&self, days: i64, day: u32) -> bool {
+        // 1970-01-01 was a thursday
+        let weekday = (days + 4).rem_euclid(7);
+
+        let day_match = self.days & (1 << day) != 0;
+        let weekday_match = self.weekdays & (1 << weekday) != 0;
+
+        if self.days_restricted && self.weekdays_restricted {
+            day_match || weekday_match
+        } else {
+            day_match && weekday_match
+        }
+    }
+}
+
+impl Calendar for Cron {
+    fn next_after(&self, after: time::SystemTime) -> Option<time::SystemTime> {
+        let after = match after.duration_since(time::UNIX_EPOCH) {
+            Ok(since) => since.as_secs() as i64,
+            Err(err) => {
+                let before = err.duration();
+                // round towards negative infinity
+                -(before.as_secs() as i64) - (before.subsec_nanos() > 0) as i64
+            }
+        };
+
+        // start with the minute following `after`
+        let mut t = after.div_euclid(60) * 60 + 60;
+
+        // every valid expression matches at least once within 8 years (leap
+        // day), give up after that
+        let limit = t + 8 * 366 * 86_400;
+
+        while t < limit {
+            let days = t.div_euclid(86_400);
+            let secs = t.rem_euclid(86_400);
+            let (year, month, day) = civil_from_days(days);
+
+            if self.months & (1 << month) == 0 {
+                let (year, month) = if month == 12 {
+                    (year + 1, 1)
+                } else {
+                    (year, month + 1)
+                };
+                t = days_from_civil(year, month, 1) * 86_400;
+                continue;
+            }
+
+            if !self.day_matches(days, day) {
+                t = (days + 1) * 86_400;
+                continue;
+            }
+
+            let hour = secs / 3600;
+            if self.hours & (1 << hour) == 0 {
+                t = days * 86_400 + (hour + 1) * 3600;
+                continue;
+            }
+
+            let minute = (secs % 3600) / 60;
+            if self.minutes & (1 << minute) == 0 {
+                t += 60;
+                continue;
+            }
+
+            return if t >= 0 {
+                time::UNIX_EPOCH.checked_add(time::Duration::from_secs(t as u64))
+            } else {
+                time::UNIX_EPOCH.checked_sub(time::Duration::from_secs(t.unsigned_abs()))
+            };
+        }
+
+        None
+    }
+}
+
+impl str::FromStr for Cron {
+    type Err = ParseCronError;
+
+    fn from_str(expr: &str) -> Result<Cron, ParseCronError> {
+        let expr = match expr.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expr => expr,
+        };
+
+        let fields: Vec<_> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(ParseCronError::new(format!(
+                "expected 5 fields, found {}",
+                fields.len()
+            )));
+        }
+
+        let mut weekdays = parse_field(fields[4], 0, 7, &WEEKDAYS)?;
+        // 7 is an alias for sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+
+        Ok(Cron {
+            minutes: parse_field(fields[0], 0, 59, &[])?,
+            hours: parse_field(fields[1], 0, 23, &[])?,
+            days: parse_field(fields[2], 1, 31, &[])?,
+            months: parse_field(fields[3], 1, 12, &MONTHS)?,
+            weekdays,
+            // like vixie cron, fields starting with `*` count as unrestricted,
+            // even with a step
+            days_restricted: !fields[2].starts_with('*'),
+            weekdays_restricted: !fields[4].starts_with('*'),
+        })
+    }
+}
+
+impl ParseCronError {
+    #[inline]
+    fn new(message: String) -> ParseCronError {
+        ParseCronError { message }
+    }
+}
+
+impl fmt::Display for ParseCronError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid cron expression: {}", self.message)
+    }
+}
+
+impl error::Error for ParseCronError {}
+
+/// Names of months, starting at 1.
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Names of days of the week, starting at 0.
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Returns a bit mask with bits `low` to `high` set.
+#[inline]
+fn bits(low: u32, high: u32) -> u64 {
+    (low..=high).fold(0, |mask, n| mask | 1 << n)
+}
+
+/// Parses a single field of a cron expression into a bit mask.
+///
+/// `names` are alternative names for values, starting at `min`.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, ParseCronError> {
+    let value = |s: &str| -> Result<u32, ParseCronError> {
+        let n = match names.iter().position(|name| name.eq_ignore_ascii_case(s)) {
+            Some(pos) => pos as u32 + min,
+            None => s
+                .parse()
+                .map_err(|_| ParseCronError::new(format!("invalid value `{}`", s)))?,
+        };
+
+        if n < min || n > max {
+            return Err(ParseCronError::new(format!(
+                "value `{}` out of range {}-{}",
+                s, min, max
+            )));
+        }
+        Ok(n)
+    };
+
+    let mut mask = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(pos) => {
+                let step: u32 = part[pos + 1..]
+                    .parse()
+                    .map_err(|_| ParseCronError::new(format!("invalid step in `{}`", part)))?;
+                if step == 0 {
+                    return Err(ParseCronError::new(format!("zero step in `{}`", part)));
+                }
+                (&part[..pos], step)
+            }
+            None => (part, 1),
+        };
+
+        let (low, high) = if range == "*" {
+            (min, max)
+        } else if let Some(pos) = range.find('-') {
+            (value(&range[..pos])?, value(&range[pos + 1..])?)
+        } else if step > 1 {
+            // `a/n` means every n-th value starting at a
+            (value(range)?, max)
+        } else {
+            let n = value(range)?;
+            (n, n)
+        };
+
+        if low > high {
+            return Err(ParseCronError::new(format!("invalid range `{}`", range)));
+        }
+
+        mask |= (low..=high)
+            .step_by(step as usize)
+            .fold(0, |mask, n| mask | 1 << n);
+    }
+
+    Ok(mask)
+}
+
+/// Converts days since the unix epoch into a (year, month, day) date.
+///
+/// See http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    (year, month, day)
+}
+
+/// Converts a (year, month, day) date into days since the unix epoch.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the system time of a UTC date and time.
+    fn utc(year: i64, month: u32, day: u32, hour: u64, minute: u64) -> time::SystemTime {
+        let days = days_from_civil(year, month, day) as u64;
+        time::UNIX_EPOCH + time::Duration::from_secs(days * 86_400 + hour * 3600 + minute * 60)
+    }
+
+    #[test]
+    fn calendar_conversion() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+
+        for days in -1000..100_000 {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+    }
+
+    #[test]
+    fn parses_expressions() {
+        let cron = Cron::parse("*/15 9-17 * jan,JUL-sep 1-5").unwrap();
+        assert_eq!(cron.minutes, 1 | 1 << 15 | 1 << 30 | 1 << 45);
+        assert_eq!(cron.hours, bits(9, 17));
+        assert_eq!(cron.months, 1 << 1 | bits(7, 9));
+        assert_eq!(cron.weekdays, bits(1, 5));
+        assert!(!cron.days_restricted);
+
+        assert_eq!(Cron::parse("0 3 * * *").unwrap(), Cron::daily_at(3, 0));
+        assert_eq!(Cron::parse("@daily").unwrap(), Cron::daily_at(0, 0));
+        assert_eq!(Cron::parse("0 0 * * 7").unwrap().weekdays, 1);
+
+        assert!(Cron::parse("* * * *").is_err());
+        assert!(Cron::parse("60 * * * *").is_err());
+        assert!(Cron::parse("* * 0 * *").is_err());
+        assert!(Cron::parse("*/0 * * * *").is_err());
+        assert!(Cron::parse("5-1 * * * *").is_err());
+        assert!(Cron::parse("* * * foo *").is_err());
+    }
+
+    #[test]
+    fn stepped_wildcards_do_not_restrict_days() {
+        // odd days that are mondays, not odd days or mondays
+        let cron = Cron::parse("0 0 */2 * MON").unwrap();
+        assert!(!cron.days_restricted);
+        assert_eq!(
+            cron.next_after(utc(2024, 1, 1, 0, 0)),
+            Some(utc(2024, 1, 15, 0, 0))
+        );
+    }
+
+    #[test]
+    fn finds_next_match() {
+        let daily = Cron::daily_at(3, 0);
+        assert_eq!(
+            daily.next_after(utc(2024, 2, 28, 12, 0)),
+            Some(utc(2024, 2, 29, 3, 0))
+        );
+        assert_eq!(
+            daily.next_after(utc(2024, 2, 29, 3, 0)),
+            Some(utc(2024, 3, 1, 3, 0))
+        );
+
+        // strictly after, even within the same minute
+        let after = utc(2024, 2, 29, 2, 59) + time::Duration::from_millis(59_999);
+        assert_eq!(daily.next_after(after), Some(utc(2024, 2, 29, 3, 0)));
+
+        let leap_day = Cron::parse("0 0 29 2 *").unwrap();
+        assert_eq!(
+            leap_day.next_after(utc(2024, 3, 1, 0, 0)),
+            Some(utc(2028, 2, 29, 0, 0))
+        );
+        assert_eq!(
+            Cron::parse("0 0 30 2 *")
+                .unwrap()
+                .next_after(time::UNIX_EPOCH),
+            None
+        );
+
+        // either day of month or day of week, 2024-06-01 is a saturday
+        let cron = Cron::parse("0 12 15 * sun").unwrap();
+        assert_eq!(
+            cron.next_after(utc(2024, 6, 1, 0, 0)),
+            Some(utc(2024, 6, 2, 12, 0))
+        );
+        assert_eq!(
+            cron.next_after(utc(2024, 6, 10, 0, 0)),
+            Some(utc(2024, 6, 15, 12, 0))
+        );
+    }
+
+    #[test]
+    fn wall_clock_converts_to_instants() {
+        let mut hourly = WallClock::new(Cron::parse("@hourly").unwrap());
+        let now = time::Instant::now();
+
+        let next = hourly.next_after(now).unwrap();
+        assert!(next > now);
+        assert!(next - now <= time::Duration::from_secs(3600));
+
+        // both clocks are read again, so allow for some drift
+        let gap = hourly.next_after(next).unwrap() - next;
+        assert!(gap > time::Duration::from_millis(3_599_900));
+        assert!(gap < time::Duration::from_millis(3_600_100));
+    }
+}
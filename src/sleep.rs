@@ -0,0 +1,232 @@
+//! Sleep strategies
+//!
+//! Sleeping through the operating system usually overshoots the requested deadline, by tens to
+//! hundreds of microseconds on Linux, more on other platforms. For high framerates, this shows up
+//! as jitter. A `SleepStrategy` trades CPU time for precision by busy-waiting for the last stretch
+//! before a deadline instead.
+//!
+//! ```
+//! use std::time;
+//! use ticktock::sleep::SleepStrategy;
+//! use ticktock::Clock;
+//!
+//! let clock = Clock::framerate(144.0).with_sleep_strategy(SleepStrategy::SpinSleep {
+//!     threshold: time::Duration::from_millis(1),
+//! });
+//!
+//! for _ in clock.iter().take(10) {
+//!     // ...
+//! }
+//!
+//! let accuracy = clock.sleep_accuracy();
+//! assert_eq!(accuracy.sleeps(), 10);
+//! println!("overshot by {:?} on average", accuracy.mean_overshoot());
+//! ```
+
+use crate::time_source::Sleeper;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time;
+
+/// Strategy for waiting until a deadline
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SleepStrategy {
+    /// Let the operating system sleep until the deadline.
+    ///
+    /// This is the default. Uses the least CPU, but is the least precise.
+    Sleep,
+    /// Sleep until `threshold` before the deadline, then busy-wait.
+    ///
+    /// The threshold should be a bit larger than the typical overshoot of
+    /// sleeping, about a millisecond is a good start.
+    SpinSleep {
+        /// Time before the deadline at which to start spinning
+        threshold: time::Duration,
+    },
+    /// Yield to other threads until the deadline, without ever sleeping.
+    ///
+    /// The most precise, but keeps a CPU core busy.
+    YieldSpin,
+}
+
+impl Default for SleepStrategy {
+    #[inline]
+    fn default() -> Self {
+        SleepStrategy::Sleep
+    }
+}
+
+impl SleepStrategy {
+    /// Waits on `source` until `deadline` has been reached
+    ///
+    /// Returns immediately if `deadline` is not in the future.
+    #[inline]
+    pub fn sleep_until<S: Sleeper>(self, source: &S, deadline: time::Instant) {
+        match self {
+            SleepStrategy::Sleep => source.sleep_until(deadline),
+            SleepStrategy::SpinSleep { threshold } => {
+                if let Some(wake_up) = deadline.checked_sub(threshold) {
+                    source.sleep_until(wake_up);
+                }
+                source.spin_until(deadline);
+            }
+            SleepStrategy::YieldSpin => source.yield_until(deadline),
+        }
+    }
+}
+
+/// Accuracy of past sleeps
+///
+/// The overshoot of a sleep is the time between its deadline and the moment
+/// the sleeping thread actually continued.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SleepAccuracy {
+    /// Number of sleeps measured
+    sleeps: u64,
+    /// Sum of all overshoots
+    total_overshoot: time::Duration,
+    /// Largest overshoot
+    max_overshoot: time::Duration,
+}
+
+impl SleepAccuracy {
+    /// Number of sleeps measured
+    ///
+    /// Sleeps to deadlines already passed are not counted.
+    #[inline]
+    pub fn sleeps(&self) -> u64 {
+        self.sleeps
+    }
+
+    /// Sum of all overshoots
+    #[inline]
+    pub fn total_overshoot(&self) -> time::Duration {
+        self.total_overshoot
+    }
+
+    /// Average overshoot
+    ///
+    /// Returns zero if nothing has been measured yet.
+    #[inline]
+    pub fn mean_overshoot(&self) -> time::Duration {
+        if self.sleeps == 0 {
+            return time::Duration::from_secs(0);
+        }
+
+        time::Duration::from_nanos((self.total_overshoot.as_nanos() / self.sleeps as u128) as u64)
+    }
+
+    /// Largest overshoot
+    #[inline]
+    pub fn max_overshoot(&self) -> time::Duration {
+        self.max_overshoot
+    }
+}
+
+/// Sleeps according to a strategy, measuring accuracy
+///
+/// Counters are atomic, so sleeping only requires a shared reference.
+#[derive(Debug, Default)]
+pub(crate) struct MeasuredSleep {
+    /// Strategy used for sleeping
+    strategy: SleepStrategy,
+    /// Number of sleeps measured
+    sleeps: AtomicU64,
+    /// Sum of all overshoots, in ns
+    total_overshoot: AtomicU64,
+    /// Largest overshoot, in ns
+    max_overshoot: AtomicU64,
+}
+
+impl MeasuredSleep {
+    /// Creates a new measured sleep, without any measurements
+    #[inline]
+    pub(crate) fn new(strategy: SleepStrategy) -> MeasuredSleep {
+        MeasuredSleep {
+            strategy,
+            ..MeasuredSleep::default()
+        }
+    }
+
+    /// Get the strategy used for sleeping
+    #[inline]
+    pub(crate) fn strategy(&self) -> SleepStrategy {
+        self.strategy
+    }
+
+    /// Sleeps on `source` until `deadline` and records the overshoot
+    pub(crate) fn sleep_until<S: Sleeper>(&self, source: &S, deadline: time::Instant) {
+        if source.now() >= deadline {
+            return;
+        }
+
+        self.strategy.sleep_until(source, deadline);
+
+        let overshoot = source.now().saturating_duration_since(deadline);
+        let overshoot = overshoot.as_nanos().min(u128::from(u64::MAX)) as u64;
+
+        self.sleeps.fetch_add(1, Ordering::Relaxed);
+        self.total_overshoot.fetch_add(overshoot, Ordering::Relaxed);
+        self.max_overshoot.fetch_max(overshoot, Ordering::Relaxed);
+    }
+
+    /// Returns the accuracy measured so far
+    #[inline]
+    pub(crate) fn accuracy(&self) -> SleepAccuracy {
+        SleepAccuracy {
+            sleeps: self.sleeps.load(Ordering::Relaxed),
+            total_overshoot: time::Duration::from_nanos(
+                self.total_overshoot.load(Ordering::Relaxed),
+            ),
+            max_overshoot: time::Duration::from_nanos(self.max_overshoot.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time_source::MockClock;
+
+    #[test]
+    fn strategies_reach_deadline() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let ms = |n| start + time::Duration::from_millis(n);
+
+        let strategies = [
+            SleepStrategy::Sleep,
+            SleepStrategy::SpinSleep {
+                threshold: time::Duration::from_millis(2),
+            },
+            SleepStrategy::YieldSpin,
+        ];
+
+        for (n, strategy) in strategies.iter().enumerate() {
+            let deadline = ms(10 * (n as u64 + 1));
+            strategy.sleep_until(&mock, deadline);
+            assert_eq!(mock.now(), deadline);
+        }
+    }
+
+    #[test]
+    fn measures_overshoot() {
+        let measured = MeasuredSleep::new(SleepStrategy::SpinSleep {
+            threshold: time::Duration::from_millis(2),
+        });
+        let source = crate::time_source::SystemClock;
+
+        for _ in 0..3 {
+            let deadline = time::Instant::now() + time::Duration::from_millis(5);
+            measured.sleep_until(&source, deadline);
+            assert!(time::Instant::now() >= deadline);
+        }
+
+        // deadlines in the past are not measured
+        measured.sleep_until(&source, time::Instant::now());
+
+        let accuracy = measured.accuracy();
+        assert_eq!(accuracy.sleeps(), 3);
+        assert!(accuracy.max_overshoot() >= accuracy.mean_overshoot());
+        assert!(accuracy.total_overshoot() >= accuracy.max_overshoot());
+    }
+}
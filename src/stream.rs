@@ -0,0 +1,342 @@
+//! Asynchronous clocks and delays
+//!
+//! Non-blocking counterparts of `ClockIter` and `Delay`, implementing
+//! `Stream`. Instead of putting the thread to sleep, a timer of the async
+//! runtime is registered and the task woken up once it expires.
+//!
+//! Timers are provided by types implementing `AsyncTimer`; `TokioTimer` is
+//! available with the `tokio` feature, `async_io::Timer` (as used by
+//! async-std) with the `async-std` feature.
+//!
+//! ```
+//! # #[cfg(feature = "tokio")]
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! use futures::StreamExt;
+//! use std::time;
+//! use ticktock::stream::TokioTimer;
+//! use ticktock::Clock;
+//!
+//! let clock = Clock::framerate(60.0);
+//! let mut ticks = clock.stream::<TokioTimer>();
+//!
+//! while let Some((tick, now)) = ticks.next().await {
+//!     // update, render, etc
+//!     // ...
+//!
+//!     break; // ignore, for doctests
+//! }
+//! # }
+//! # #[cfg(not(feature = "tokio"))]
+//! # fn main() {}
+//! ```
+
+use crate::clock::{Clock, TickState};
+use crate::time_source::{SystemClock, TimeSource};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time;
+
+/// A timer of an async runtime
+pub trait AsyncTimer: Unpin {
+    /// Creates a timer expiring at `deadline`.
+    fn at(deadline: time::Instant) -> Self;
+
+    /// Rearms the timer to expire at `deadline`.
+    fn reset(&mut self, deadline: time::Instant);
+
+    /// Polls the timer, returning `Poll::Ready` once it has expired.
+    fn poll_expired(&mut self, cx: &mut Context<'_>) -> Poll<()>;
+}
+
+/// Timer backed by the tokio runtime
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct TokioTimer(Pin<Box<tokio::time::Sleep>>);
+
+#[cfg(feature = "tokio")]
+impl AsyncTimer for TokioTimer {
+    #[inline]
+    fn at(deadline: time::Instant) -> Self {
+        TokioTimer(Box::pin(tokio::time::sleep_until(deadline.into())))
+    }
+
+    #[inline]
+    fn reset(&mut self, deadline: time::Instant) {
+        self.0.as_mut().reset(deadline.into())
+    }
+
+    #[inline]
+    fn poll_expired(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        std::future::Future::poll(self.0.as_mut(), cx)
+    }
+}
+
+#[cfg(feature = "async-std")]
+impl AsyncTimer for async_io::Timer {
+    #[inline]
+    fn at(deadline: time::Instant) -> Self {
+        async_io::Timer::at(deadline)
+    }
+
+    #[inline]
+    fn reset(&mut self, deadline: time::Instant) {
+        self.set_at(deadline)
+    }
+
+    #[inline]
+    fn poll_expired(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        std::future::Future::poll(Pin::new(self), cx).map(|_| ())
+    }
+}
+
+/// Arms `timer` for `deadline`, creating it if necessary
+#[inline]
+pub(crate) fn arm<T: AsyncTimer>(timer: &mut Option<T>, deadline: time::Instant) {
+    match timer {
+        Some(timer) => timer.reset(deadline),
+        None => *timer = Some(T::at(deadline)),
+    }
+}
+
+/// A clock stream
+///
+/// Asynchronous version of `ClockIter`, created by `Clock::stream`. Yields
+/// `(tick number, absolute time)` for every tick, handling missed ticks
+/// according to the clock's `MissedTickBehavior`.
+pub struct ClockStream<'a, T, S = SystemClock> {
+    /// Underlying clock
+    clock: &'a Clock<S>,
+    /// Tick bookkeeping
+    state: TickState,
+    /// Timer, created on first use
+    timer: Option<T>,
+    /// Tick currently being waited for
+    pending: Option<(u128, time::Instant)>,
+}
+
+impl<'a, T, S> ClockStream<'a, T, S>
+where
+    S: TimeSource,
+{
+    /// Creates a new stream over `clock`
+    #[inline]
+    pub(crate) fn new(clock: &'a Clock<S>) -> ClockStream<'a, T, S> {
+        ClockStream {
+            clock,
+            state: TickState::new(clock),
+            timer: None,
+            pending: None,
+        }
+    }
+
+    /// Number of ticks dropped before the most recently returned tick
+    ///
+    /// See `ClockIter::missed_ticks`.
+    #[inline]
+    pub fn missed_ticks(&self) -> u128 {
+        self.state.missed_ticks()
+    }
+
+    /// Total number of ticks dropped since the stream was created
+    #[inline]
+    pub fn total_missed_ticks(&self) -> u128 {
+        self.state.total_missed_ticks()
+    }
+}
+
+impl<'a, T, S> Stream for ClockStream<'a, T, S>
+where
+    T: AsyncTimer,
+    S: TimeSource,
+{
+    type Item = (u128, time::Instant);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        let (tick_num, tick) = match this.pending {
+            Some(pending) => pending,
+            None => {
+                let now = this.clock.source().now();
+                let (tick_num, tick) = this.state.schedule(this.clock, now);
+
+                // missed ticks that are caught up on do not need a timer
+                if tick <= now {
+                    return Poll::Ready(Some((tick_num, tick)));
+                }
+
+                arm(&mut this.timer, tick);
+                this.pending = Some((tick_num, tick));
+                (tick_num, tick)
+            }
+        };
+
+        if let Some(timer) = this.timer.as_mut() {
+            if timer.poll_expired(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+
+        this.pending = None;
+        Poll::Ready(Some((tick_num, tick)))
+    }
+}
+
+/// A delay stream
+///
+/// Asynchronous version of `Delay`, created by `Delay::stream`.
+pub struct DelayStream<T, S = SystemClock> {
+    /// Delay duration
+    delay: time::Duration,
+    /// Whether or not the first item is still to be returned without delay
+    first_tick: bool,
+    /// Time source used to calculate deadlines.
+    source: S,
+    /// Timer, created on first use
+    timer: Option<T>,
+    /// Whether or not the timer is currently armed
+    waiting: bool,
+}
+
+impl<T, S> DelayStream<T, S> {
+    /// Creates a new delay stream
+    #[inline]
+    pub(crate) fn new(delay: time::Duration, first_tick: bool, source: S) -> DelayStream<T, S> {
+        DelayStream {
+            delay,
+            first_tick,
+            source,
+            timer: None,
+            waiting: false,
+        }
+    }
+}
+
+impl<T, S> Stream for DelayStream<T, S>
+where
+    T: AsyncTimer,
+    S: TimeSource + Unpin,
+{
+    type Item = ();
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if this.first_tick {
+            this.first_tick = false;
+            return Poll::Ready(Some(()));
+        }
+
+        if !this.waiting {
+            arm(&mut this.timer, this.source.now() + this.delay);
+            this.waiting = true;
+        }
+
+        if let Some(timer) = this.timer.as_mut() {
+            if timer.poll_expired(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+
+        this.waiting = false;
+        Poll::Ready(Some(()))
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+    use crate::clock::MissedTickBehavior;
+    use crate::delay::Delay;
+    use crate::time_source::TokioClock;
+    use futures::StreamExt;
+
+    #[tokio::test(start_paused = true)]
+    async fn clock_stream_ticks() {
+        let start = TokioClock.now();
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), TokioClock);
+
+        let ticks: Vec<_> = clock.stream::<TokioTimer>().take(3).collect().await;
+        assert_eq!(
+            ticks,
+            vec![
+                (1, start + time::Duration::from_millis(10)),
+                (2, start + time::Duration::from_millis(20)),
+                (3, start + time::Duration::from_millis(30)),
+            ]
+        );
+        assert_eq!(TokioClock.now() - start, time::Duration::from_millis(30));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clock_stream_handles_missed_ticks() {
+        let start = TokioClock.now();
+        let clock = Clock::new_with_source(time::Duration::from_millis(10), TokioClock)
+            .with_missed_tick_behavior(MissedTickBehavior::Burst);
+        let mut ticks = clock.stream::<TokioTimer>();
+
+        tokio::time::advance(time::Duration::from_millis(25)).await;
+        assert_eq!(ticks.next().await.unwrap().0, 1);
+        assert_eq!(ticks.next().await.unwrap().0, 2);
+        assert_eq!(
+            ticks.next().await.unwrap(),
+            (3, start + time::Duration::from_millis(30))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn delay_stream_delays_after_first() {
+        let start = TokioClock.now();
+        let mut delay =
+            Delay::new_with_source(time::Duration::from_secs(1), TokioClock).stream::<TokioTimer>();
+
+        delay.next().await;
+        assert_eq!(TokioClock.now(), start);
+
+        delay.next().await;
+        delay.next().await;
+        assert_eq!(TokioClock.now() - start, time::Duration::from_secs(2));
+    }
+}
+
+#[cfg(all(test, feature = "async-std"))]
+mod async_std_tests {
+    use super::*;
+    use crate::delay::Delay;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+
+    #[test]
+    fn clock_stream_ticks() {
+        let start = time::Instant::now();
+        let clock = Clock::new_with_start_time(time::Duration::from_millis(10), start);
+
+        let ticks: Vec<_> = block_on(clock.stream::<async_io::Timer>().take(3).collect());
+        assert_eq!(
+            ticks,
+            vec![
+                (1, start + time::Duration::from_millis(10)),
+                (2, start + time::Duration::from_millis(20)),
+                (3, start + time::Duration::from_millis(30)),
+            ]
+        );
+        assert!(time::Instant::now() >= start + time::Duration::from_millis(30));
+    }
+
+    #[test]
+    fn delay_stream_delays_after_first() {
+        let start = time::Instant::now();
+        let mut delay = Delay::new(time::Duration::from_millis(10)).stream::<async_io::Timer>();
+
+        block_on(async {
+            delay.next().await;
+            assert!(time::Instant::now() - start < time::Duration::from_millis(10));
+
+            delay.next().await;
+            delay.next().await;
+        });
+        assert!(time::Instant::now() - start >= time::Duration::from_millis(20));
+    }
+}
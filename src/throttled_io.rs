@@ -2,9 +2,36 @@
 //!
 //! This module allows simulating limited bandwidth by lengthening the duration
 //! of calls to `Read`/`Write` to meet a specific upper bound on the rate.
+//!
+//! With the `tokio` or `async-std` feature enabled, `AsyncThrottledIo` offers
+//! the same for asynchronous IO, implementing `futures::AsyncRead`/`AsyncWrite`
+//! and, with the `tokio` feature, tokio's `AsyncRead`/`AsyncWrite`.
+//!
+//! Rates are enforced by a token bucket, see `RateLimiter`, which can also be
+//! used on its own. Any type implementing `Limiter` can be used instead, e.g.
+//! a `SharedLimiter` to split a single bandwidth budget between multiple
+//! wrappers.
+//!
+//! Other properties of a bad connection, such as latency, jitter or transient
+//! errors, can be simulated using `ImpairedIo`.
+//!
+//! Both wrappers keep transfer statistics, see `Stats`.
 
+use crate::time_source::{Sleeper, SystemClock};
 use std::io::{Read, Write};
-use std::{io, thread, time};
+use std::{io, time};
+
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+mod asynchronous;
+mod impaired;
+mod shared;
+mod stats;
+
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub use self::asynchronous::AsyncThrottledIo;
+pub use self::impaired::{ImpairedIo, Impairments};
+pub use self::shared::{Allocation, SharedLimiter};
+pub use self::stats::{Stats, TransferStats};
 
 const NS_PER_SECOND: u128 = 1_000_000_000;
 
@@ -12,18 +39,42 @@ const NS_PER_SECOND: u128 = 1_000_000_000;
 ///
 /// When asked to read bytes, the reader will always pause after a successful
 /// read to never exceed the specified maximum read rate.
+///
+/// Reads and writes are limited independently, each by its own limiter. For
+/// the default `RateLimiter`, the rate can be changed at any time or lifted
+/// entirely.
+///
+/// By default, no burst is allowed, i.e. time spent idle does not allow for
+/// faster transfers later.
+///
+/// An asymmetric link, such as ADSL, can be simulated by using different
+/// limiters for each direction:
+///
+/// ```
+/// use std::io::{self, Read};
+/// use ticktock::throttled_io::{RateLimiter, ThrottledIo};
+///
+/// // 2 mb/s down, 100 kb/s up
+/// let mut adsl = ThrottledIo::new_with_limiters(
+///     io::repeat(0),
+///     RateLimiter::new(2_000_000, 0),
+///     RateLimiter::new(100_000, 0),
+/// );
+///
+/// let mut buf = [0; 1000];
+/// adsl.read(&mut buf).unwrap();
+///
+/// // disable throttling of reads
+/// adsl.set_read_rate(None);
+/// ```
 #[derive(Debug)]
-pub struct ThrottledIo<T> {
-    /// Desired nanoseconds per byte.
-    bytes_per_second: u32,
-    /// Total bytes read since `start`.
-    total_read: u128,
-    /// Total bytes written since `start`.
-    total_written: u128,
-    /// Start time.
-    start: time::Instant,
+pub struct ThrottledIo<T, S = SystemClock, L = RateLimiter> {
+    /// Bandwidth accounting.
+    throttle: Throttle<L>,
     /// Inner IO type.
     io: T,
+    /// Time source used for measuring and sleeping.
+    source: S,
 }
 
 impl<T> ThrottledIo<T> {
@@ -35,62 +86,176 @@ impl<T> ThrottledIo<T> {
 
     /// Create a new throttled reader, with specified start time.
     ///
-    /// Note: If `now` is in the future, calls to `read` will likely panic.
+    /// If `now` is in the future, no bandwidth is available before it: the
+    /// first transfer waits until `now` plus the time the transferred bytes
+    /// cost at the given rate.
     #[inline]
     pub fn new_with_start_time(io: T, bytes_per_second: u32, now: time::Instant) -> ThrottledIo<T> {
+        Self::new_with_limiter(
+            io,
+            RateLimiter::new_with_start_time(bytes_per_second, 0, now),
+        )
+    }
+}
+
+impl<T, L> ThrottledIo<T, SystemClock, L>
+where
+    L: Limiter,
+{
+    /// Create a new throttled reader, limited by a custom rate limiter.
+    ///
+    /// Reads and writes are limited by separate clones of `limiter`. Note
+    /// that a clone of a `SharedLimiter` is a separate handle of its budget.
+    #[inline]
+    pub fn new_with_limiter(io: T, limiter: L) -> ThrottledIo<T, SystemClock, L>
+    where
+        L: Clone,
+    {
+        Self::new_with_limiter_and_source(io, limiter, SystemClock)
+    }
+
+    /// Create a new throttled reader, with separate limiters for reads and
+    /// writes.
+    #[inline]
+    pub fn new_with_limiters(io: T, read: L, write: L) -> ThrottledIo<T, SystemClock, L> {
+        Self::new_with_limiters_and_source(io, read, write, SystemClock)
+    }
+}
+
+impl<T, S> ThrottledIo<T, S>
+where
+    S: Sleeper,
+{
+    /// Create a new throttled reader, using a custom time source.
+    #[inline]
+    pub fn new_with_source(io: T, bytes_per_second: u32, source: S) -> ThrottledIo<T, S> {
+        let limiter = RateLimiter::new_with_start_time(bytes_per_second, 0, source.now());
+        Self::new_with_limiter_and_source(io, limiter, source)
+    }
+
+    /// Change the maximum read rate, `None` meaning unlimited.
+    ///
+    /// See `RateLimiter::set_rate`.
+    #[inline]
+    pub fn set_read_rate(&mut self, bytes_per_second: Option<u32>) {
+        self.throttle
+            .read
+            .set_rate(bytes_per_second, self.source.now())
+    }
+
+    /// Change the maximum write rate, `None` meaning unlimited.
+    ///
+    /// See `RateLimiter::set_rate`.
+    #[inline]
+    pub fn set_write_rate(&mut self, bytes_per_second: Option<u32>) {
+        self.throttle
+            .write
+            .set_rate(bytes_per_second, self.source.now())
+    }
+}
+
+impl<T, S, L> ThrottledIo<T, S, L>
+where
+    S: Sleeper,
+    L: Limiter,
+{
+    /// Create a new throttled reader, limited by a custom rate limiter and
+    /// using a custom time source.
+    #[inline]
+    pub fn new_with_limiter_and_source(io: T, limiter: L, source: S) -> ThrottledIo<T, S, L>
+    where
+        L: Clone,
+    {
+        Self::new_with_limiters_and_source(io, limiter.clone(), limiter, source)
+    }
+
+    /// Create a new throttled reader, with separate limiters for reads and
+    /// writes and using a custom time source.
+    #[inline]
+    pub fn new_with_limiters_and_source(
+        io: T,
+        read: L,
+        write: L,
+        source: S,
+    ) -> ThrottledIo<T, S, L> {
         ThrottledIo {
-            bytes_per_second,
-            total_read: 0,
-            total_written: 0,
-            start: now,
+            throttle: Throttle::new(read, write, source.now()),
             io,
+            source,
         }
     }
 
-    /// Return the inner reader/writer.
+    /// Get the limiter used for reads.
     #[inline]
-    pub fn into_inner(self) -> T {
-        self.io
+    pub fn read_limiter(&self) -> &L {
+        &self.throttle.read
     }
 
+    /// Get the limiter used for writes.
     #[inline]
-    fn delay(&self, total: u128) {
-        let elapsed = time::Instant::now() - self.start;
-        let max_bytes = (elapsed.as_nanos() * self.bytes_per_second as u128) / NS_PER_SECOND;
+    pub fn write_limiter(&self) -> &L {
+        &self.throttle.write
+    }
 
-        // Delay until we're actually supposed to be done.
-        if max_bytes < total {
-            let remainder_ns = (total - max_bytes) * NS_PER_SECOND / self.bytes_per_second as u128;
+    /// Get the transfer statistics.
+    #[inline]
+    pub fn stats(&self) -> &Stats {
+        &self.throttle.stats
+    }
 
-            thread::sleep(time::Duration::from_nanos(remainder_ns as u64))
-        }
+    /// Resets the transfer statistics, starting a new measurement.
+    #[inline]
+    pub fn reset_stats(&mut self) {
+        let window = self.throttle.stats.read().window();
+        self.throttle.stats = Stats::new(self.source.now());
+        self.throttle.stats.set_window(window);
+    }
+
+    /// Sets the length of the window used to calculate current rates.
+    ///
+    /// Defaults to one second. Panics if `window` is zero.
+    #[inline]
+    pub fn set_stats_window(&mut self, window: time::Duration) {
+        self.throttle.stats.set_window(window)
+    }
+
+    /// Return the inner reader/writer.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.io
     }
 }
 
-impl<T> Read for ThrottledIo<T>
+impl<T, S, L> Read for ThrottledIo<T, S, L>
 where
     T: Read,
+    S: Sleeper,
+    L: Limiter,
 {
     #[inline]
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         let bytes_read = self.io.read(buf)?;
-        self.total_read += bytes_read as u128;
 
-        self.delay(self.total_read);
+        // Delay until we're actually supposed to be done.
+        let now = self.source.now();
+        self.source.sleep_until(self.throttle.read(bytes_read, now));
 
         Ok(bytes_read)
     }
 }
 
-impl<T> Write for ThrottledIo<T>
+impl<T, S, L> Write for ThrottledIo<T, S, L>
 where
     T: Write,
+    S: Sleeper,
+    L: Limiter,
 {
     fn write(&mut self, data: &[u8]) -> io::Result<usize> {
         let bytes_written = self.io.write(data)?;
-        self.total_written += bytes_written as u128;
 
-        self.delay(self.total_written);
+        let now = self.source.now();
+        self.source
+            .sleep_until(self.throttle.write(bytes_written, now));
 
         Ok(bytes_written)
     }
@@ -99,3 +264,416 @@ where
         self.io.flush()
     }
 }
+
+/// A limiter of transfer rates.
+pub trait Limiter {
+    /// Records a transfer of `bytes` at `now`.
+    ///
+    /// Returns the duration to wait before the transfer is considered
+    /// complete.
+    fn acquire(&mut self, bytes: u64, now: time::Instant) -> time::Duration;
+}
+
+/// A token bucket rate limiter.
+///
+/// The bucket holds up to `burst` bytes and is refilled at a rate of
+/// `bytes_per_second`. Transfers take bytes out of the bucket, going into debt
+/// if there are not enough. The time until the debt has been paid off is the
+/// time the caller should wait.
+///
+/// Since the bucket never holds more than `burst` bytes, time spent idle
+/// never allows for more than a burst of `burst` bytes.
+///
+/// ```
+/// use std::time;
+/// use ticktock::throttled_io::RateLimiter;
+///
+/// let now = time::Instant::now();
+/// let mut limiter = RateLimiter::new_with_start_time(100, 50, now);
+///
+/// // the first 50 bytes can be sent right away, the others take a second
+/// assert_eq!(limiter.acquire(50, now), time::Duration::from_secs(0));
+/// assert_eq!(limiter.acquire(100, now), time::Duration::from_secs(1));
+/// ```
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    /// Refill rate, `None` if unlimited.
+    bytes_per_second: Option<u32>,
+    /// Bucket capacity, in bytes.
+    burst: u32,
+    /// Instant at which all bytes taken have been paid for.
+    ///
+    /// The bucket is full once this lies `burst / bytes_per_second` in the past.
+    paid_until: time::Instant,
+}
+
+impl RateLimiter {
+    /// Creates a new rate limiter with a full bucket.
+    ///
+    /// `bytes_per_second` must not be zero.
+    #[inline]
+    pub fn new(bytes_per_second: u32, burst: u32) -> RateLimiter {
+        RateLimiter::new_with_start_time(bytes_per_second, burst, time::Instant::now())
+    }
+
+    /// Creates a new rate limiter with a bucket that is full at `now`.
+    #[inline]
+    pub fn new_with_start_time(
+        bytes_per_second: u32,
+        burst: u32,
+        now: time::Instant,
+    ) -> RateLimiter {
+        let mut limiter = RateLimiter::unlimited(burst, now);
+        limiter.set_rate(Some(bytes_per_second), now);
+        limiter
+    }
+
+    /// Creates a new rate limiter that does not limit.
+    ///
+    /// `burst` is used once a rate is set.
+    #[inline]
+    pub fn unlimited(burst: u32, now: time::Instant) -> RateLimiter {
+        RateLimiter {
+            bytes_per_second: None,
+            burst,
+            paid_until: now,
+        }
+    }
+
+    /// Refill rate in bytes per second, `None` if unlimited.
+    #[inline]
+    pub fn bytes_per_second(&self) -> Option<u32> {
+        self.bytes_per_second
+    }
+
+    /// Bucket capacity in bytes.
+    #[inline]
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// Changes the refill rate, `None` meaning unlimited.
+    ///
+    /// The contents of the bucket (or the debt) are carried over, i.e. bytes
+    /// not yet paid for at the old rate will be paid off at the new rate,
+    /// starting at `now`. When switching from unlimited to a limited rate,
+    /// the bucket starts out full.
+    ///
+    /// Panics if `bytes_per_second` is zero.
+    pub fn set_rate(&mut self, bytes_per_second: Option<u32>, now: time::Instant) {
+        assert_ne!(bytes_per_second, Some(0), "rate must not be zero");
+
+        if self.bytes_per_second.is_none() {
+            self.bytes_per_second = bytes_per_second;
+            self.paid_until = self.full_at(now);
+            return;
+        }
+
+        let paid_until = self.paid_until.max(self.full_at(now));
+
+        if paid_until > now {
+            let debt = self.bytes_for(paid_until - now);
+            self.bytes_per_second = bytes_per_second;
+            self.paid_until = now + self.cost(debt);
+        } else {
+            let credit = self.bytes_for(now - paid_until);
+            self.bytes_per_second = bytes_per_second;
+            self.paid_until = now.checked_sub(self.cost(credit)).unwrap_or(now);
+        }
+    }
+
+    /// Number of bytes that can be taken at `now` without having to wait.
+    ///
+    /// Returns `u64::MAX` if unlimited.
+    #[inline]
+    pub fn available(&self, now: time::Instant) -> u64 {
+        if self.bytes_per_second.is_none() {
+            return u64::MAX;
+        }
+
+        let paid_until = self.paid_until.max(self.full_at(now));
+
+        if paid_until >= now {
+            return 0;
+        }
+
+        self.bytes_for(now - paid_until)
+    }
+
+    /// Takes `bytes` out of the bucket.
+    ///
+    /// Returns the duration to wait until the bytes have been paid for, which
+    /// is zero if there were enough bytes in the bucket.
+    #[inline]
+    pub fn acquire(&mut self, bytes: u64, now: time::Instant) -> time::Duration {
+        if self.bytes_per_second.is_none() {
+            return time::Duration::from_secs(0);
+        }
+
+        self.paid_until = self.paid_until.max(self.full_at(now)) + self.cost(bytes);
+
+        self.paid_until.saturating_duration_since(now)
+    }
+
+    /// Time it takes to refill `bytes`.
+    #[inline]
+    fn cost(&self, bytes: u64) -> time::Duration {
+        match self.bytes_per_second {
+            Some(rate) => {
+                let ns = bytes as u128 * NS_PER_SECOND / rate as u128;
+                time::Duration::from_nanos(ns as u64)
+            }
+            None => time::Duration::from_secs(0),
+        }
+    }
+
+    /// Number of bytes refilled in `duration`.
+    #[inline]
+    fn bytes_for(&self, duration: time::Duration) -> u64 {
+        match self.bytes_per_second {
+            Some(rate) => (duration.as_nanos() * rate as u128 / NS_PER_SECOND) as u64,
+            None => u64::MAX,
+        }
+    }
+
+    /// Value of `paid_until` that corresponds to a full bucket at `now`.
+    #[inline]
+    fn full_at(&self, now: time::Instant) -> time::Instant {
+        now.checked_sub(self.cost(self.burst as u64)).unwrap_or(now)
+    }
+}
+
+impl Limiter for RateLimiter {
+    #[inline]
+    fn acquire(&mut self, bytes: u64, now: time::Instant) -> time::Duration {
+        RateLimiter::acquire(self, bytes, now)
+    }
+}
+
+/// Bandwidth accounting, shared by the blocking and asynchronous wrappers.
+#[derive(Debug)]
+struct Throttle<L> {
+    /// Limiter for reads.
+    read: L,
+    /// Limiter for writes.
+    write: L,
+    /// Transfer statistics.
+    stats: Stats,
+}
+
+impl<L> Throttle<L>
+where
+    L: Limiter,
+{
+    #[inline]
+    fn new(read: L, write: L, now: time::Instant) -> Throttle<L> {
+        Throttle {
+            read,
+            write,
+            stats: Stats::new(now),
+        }
+    }
+
+    /// Records a read, returning the instant at which it should be completed.
+    #[inline]
+    fn read(&mut self, bytes: usize, now: time::Instant) -> time::Instant {
+        let completed_at = now + self.read.acquire(bytes as u64, now);
+        self.stats
+            .read_mut()
+            .record(bytes as u64, now, completed_at);
+        completed_at
+    }
+
+    /// Records a write, returning the instant at which it should be completed.
+    #[inline]
+    fn write(&mut self, bytes: usize, now: time::Instant) -> time::Instant {
+        let completed_at = now + self.write.acquire(bytes as u64, now);
+        self.stats
+            .write_mut()
+            .record(bytes as u64, now, completed_at);
+        completed_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time_source::MockClock;
+
+    #[test]
+    fn throttles_reads_and_writes() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let mut tio =
+            ThrottledIo::new_with_source(io::Cursor::new(vec![0; 1000]), 100, mock.clone());
+
+        let mut buf = [0; 50];
+        assert_eq!(tio.read(&mut buf).unwrap(), 50);
+        assert_eq!(mock.now() - start, time::Duration::from_millis(500));
+
+        assert_eq!(tio.write(&buf[..20]).unwrap(), 20);
+        assert_eq!(mock.now() - start, time::Duration::from_millis(700));
+
+        assert_eq!(tio.write(&buf).unwrap(), 50);
+        assert_eq!(mock.now() - start, time::Duration::from_millis(1200));
+    }
+
+    #[test]
+    fn idle_time_does_not_accumulate() {
+        let mock = MockClock::new();
+        let mut tio =
+            ThrottledIo::new_with_source(io::Cursor::new(vec![0; 1000]), 100, mock.clone());
+
+        mock.advance(time::Duration::from_secs(10));
+        let start = mock.now();
+
+        let mut buf = [0; 50];
+        assert_eq!(tio.read(&mut buf).unwrap(), 50);
+        assert_eq!(mock.now() - start, time::Duration::from_millis(500));
+    }
+
+    #[test]
+    fn future_start_time_delays_first_transfer() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let limiter =
+            RateLimiter::new_with_start_time(100, 0, start + time::Duration::from_secs(1));
+        let mut tio =
+            ThrottledIo::new_with_limiter_and_source(io::repeat(0), limiter, mock.clone());
+
+        let mut buf = [0; 50];
+        assert_eq!(tio.read(&mut buf).unwrap(), 50);
+        assert_eq!(mock.now() - start, time::Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn allows_configured_burst() {
+        let mock = MockClock::new();
+        let limiter = RateLimiter::new_with_start_time(100, 50, mock.now());
+        let mut tio = ThrottledIo::new_with_limiter_and_source(
+            io::Cursor::new(vec![0; 1000]),
+            limiter,
+            mock.clone(),
+        );
+
+        mock.advance(time::Duration::from_secs(10));
+        let start = mock.now();
+
+        let mut buf = [0; 50];
+        assert_eq!(tio.read(&mut buf).unwrap(), 50);
+        assert_eq!(mock.now(), start);
+        assert_eq!(tio.read(&mut buf).unwrap(), 50);
+        assert_eq!(mock.now() - start, time::Duration::from_millis(500));
+    }
+
+    #[test]
+    fn separate_read_and_write_rates() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let mut tio = ThrottledIo::new_with_limiters_and_source(
+            io::Cursor::new(vec![0; 1000]),
+            RateLimiter::new_with_start_time(1000, 0, start),
+            RateLimiter::unlimited(0, start),
+            mock.clone(),
+        );
+
+        let mut buf = [0; 100];
+        assert_eq!(tio.read(&mut buf).unwrap(), 100);
+        assert_eq!(mock.now() - start, time::Duration::from_millis(100));
+
+        assert_eq!(tio.write(&buf).unwrap(), 100);
+        assert_eq!(mock.now() - start, time::Duration::from_millis(100));
+
+        tio.set_write_rate(Some(100));
+        assert_eq!(tio.write(&buf).unwrap(), 100);
+        assert_eq!(mock.now() - start, time::Duration::from_millis(1100));
+
+        tio.set_read_rate(None);
+        assert_eq!(tio.read(&mut buf).unwrap(), 100);
+        assert_eq!(mock.now() - start, time::Duration::from_millis(1100));
+    }
+
+    #[test]
+    fn stats_reflect_throttling() {
+        let mock = MockClock::new();
+        let mut tio =
+            ThrottledIo::new_with_source(io::Cursor::new(vec![0; 1000]), 200, mock.clone());
+
+        let mut buf = [0; 50];
+        for _ in 0..20 {
+            tio.read_exact(&mut buf).unwrap();
+        }
+        assert_eq!(tio.stats().read().average_rate(mock.now()), 200.0);
+
+        tio.write_all(&buf).unwrap();
+
+        let stats = tio.stats();
+        assert_eq!(stats.read().total_bytes(), 1000);
+        assert_eq!(stats.read().current_rate(mock.now()), 150.0);
+        assert_eq!(stats.write().total_bytes(), 50);
+        assert_eq!(
+            stats.write().time_sleeping(),
+            time::Duration::from_millis(250)
+        );
+
+        tio.reset_stats();
+        assert_eq!(tio.stats().read().total_bytes(), 0);
+        assert_eq!(tio.stats().read().started_at(), mock.now());
+    }
+
+    #[test]
+    fn rate_change_carries_over_debt() {
+        let now = time::Instant::now();
+        let mut limiter = RateLimiter::new_with_start_time(100, 0, now);
+
+        // 100 bytes take one second at 100 b/s, half of it has passed
+        limiter.acquire(100, now);
+        let later = now + time::Duration::from_millis(500);
+
+        // remaining 50 bytes of debt take a quarter second at 200 b/s
+        limiter.set_rate(Some(200), later);
+        assert_eq!(limiter.acquire(0, later), time::Duration::from_millis(250));
+        assert_eq!(limiter.bytes_per_second(), Some(200));
+    }
+
+    #[test]
+    fn rate_change_carries_over_credit() {
+        let now = time::Instant::now();
+        let mut limiter = RateLimiter::new_with_start_time(100, 100, now);
+
+        limiter.acquire(50, now);
+        limiter.set_rate(Some(1000), now);
+        assert_eq!(limiter.available(now), 50);
+
+        limiter.set_rate(None, now);
+        assert_eq!(limiter.available(now), u64::MAX);
+        assert_eq!(
+            limiter.acquire(1_000_000, now),
+            time::Duration::from_secs(0)
+        );
+    }
+
+    #[test]
+    fn rate_limiter_refills_up_to_burst() {
+        let now = time::Instant::now();
+        let mut limiter = RateLimiter::new_with_start_time(100, 50, now);
+
+        assert_eq!(limiter.available(now), 50);
+        assert_eq!(limiter.acquire(80, now), time::Duration::from_millis(300));
+        assert_eq!(limiter.available(now), 0);
+
+        let later = now + time::Duration::from_millis(500);
+        assert_eq!(limiter.available(later), 20);
+
+        let much_later = now + time::Duration::from_secs(60);
+        assert_eq!(limiter.available(much_later), 50);
+        assert_eq!(
+            limiter.acquire(50, much_later),
+            time::Duration::from_secs(0)
+        );
+        assert_eq!(
+            limiter.acquire(10, much_later),
+            time::Duration::from_millis(100)
+        );
+    }
+}
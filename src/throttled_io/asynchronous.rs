@@ -0,0 +1,465 @@
+//! Asynchronous throttled IO.
+
+use super::{Limiter, RateLimiter, Stats, Throttle};
+use crate::stream::{arm, AsyncTimer};
+use crate::time_source::{SystemClock, TimeSource};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::{fmt, io, time};
+
+/// An asynchronous wrapper that limits the maximum read/write-rate.
+///
+/// Instead of pausing after a transfer, the next read or write is delayed
+/// until the previous one would have completed at the specified rate, by
+/// registering a timer of type `A` (see the `stream` module). Flushing and
+/// closing also wait for the delay of the last write to pass.
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// use tokio::io::AsyncReadExt;
+/// use ticktock::stream::TokioTimer;
+/// use ticktock::throttled_io::AsyncThrottledIo;
+///
+/// let data: &[u8] = b"hello, world";
+/// let mut slow = AsyncThrottledIo::<_, TokioTimer>::new(data, 1000);
+///
+/// let mut buf = Vec::new();
+/// slow.read_to_end(&mut buf).await.unwrap();
+/// assert_eq!(buf, b"hello, world");
+/// # }
+/// # #[cfg(not(feature = "tokio"))]
+/// # fn main() {}
+/// ```
+pub struct AsyncThrottledIo<T, A, S = SystemClock, L = RateLimiter> {
+    /// Bandwidth accounting.
+    throttle: Throttle<L>,
+    /// Inner IO type.
+    io: T,
+    /// Time source used for measuring.
+    source: S,
+    /// Delay before the next read.
+    read_gate: Gate<A>,
+    /// Delay before the next write.
+    write_gate: Gate<A>,
+}
+
+impl<T, A> AsyncThrottledIo<T, A> {
+    /// Create a new throttled reader with a specified maximum rate.
+    #[inline]
+    pub fn new(io: T, bytes_per_second: u32) -> AsyncThrottledIo<T, A> {
+        Self::new_with_source(io, bytes_per_second, SystemClock)
+    }
+}
+
+impl<T, A, L> AsyncThrottledIo<T, A, SystemClock, L>
+where
+    L: Limiter,
+{
+    /// Create a new throttled reader, limited by a custom rate limiter.
+    ///
+    /// Reads and writes are limited by separate clones of `limiter`.
+    #[inline]
+    pub fn new_with_limiter(io: T, limiter: L) -> AsyncThrottledIo<T, A, SystemClock, L>
+    where
+        L: Clone,
+    {
+        Self::new_with_limiter_and_source(io, limiter, SystemClock)
+    }
+
+    /// Create a new throttled reader, with separate limiters for reads and
+    /// writes.
+    #[inline]
+    pub fn new_with_limiters(io: T, read: L, write: L) -> AsyncThrottledIo<T, A, SystemClock, L> {
+        Self::new_with_limiters_and_source(io, read, write, SystemClock)
+    }
+}
+
+impl<T, A, S> AsyncThrottledIo<T, A, S>
+where
+    S: TimeSource,
+{
+    /// Create a new throttled reader, using a custom time source.
+    #[inline]
+    pub fn new_with_source(io: T, bytes_per_second: u32, source: S) -> AsyncThrottledIo<T, A, S> {
+        let limiter = RateLimiter::new_with_start_time(bytes_per_second, 0, source.now());
+        Self::new_with_limiter_and_source(io, limiter, source)
+    }
+
+    /// Change the maximum read rate, `None` meaning unlimited.
+    ///
+    /// See `RateLimiter::set_rate`. Takes effect after a pending delay.
+    #[inline]
+    pub fn set_read_rate(&mut self, bytes_per_second: Option<u32>) {
+        self.throttle
+            .read
+            .set_rate(bytes_per_second, self.source.now())
+    }
+
+    /// Change the maximum write rate, `None` meaning unlimited.
+    ///
+    /// See `RateLimiter::set_rate`. Takes effect after a pending delay.
+    #[inline]
+    pub fn set_write_rate(&mut self, bytes_per_second: Option<u32>) {
+        self.throttle
+            .write
+            .set_rate(bytes_per_second, self.source.now())
+    }
+}
+
+impl<T, A, S, L> AsyncThrottledIo<T, A, S, L>
+where
+    S: TimeSource,
+    L: Limiter,
+{
+    /// Create a new throttled reader, limited by a custom rate limiter and
+    /// using a custom time source.
+    #[inline]
+    pub fn new_with_limiter_and_source(io: T, limiter: L, source: S) -> AsyncThrottledIo<T, A, S, L>
+    where
+        L: Clone,
+    {
+        Self::new_with_limiters_and_source(io, limiter.clone(), limiter, source)
+    }
+
+    /// Create a new throttled reader, with separate limiters for reads and
+    /// writes and using a custom time source.
+    #[inline]
+    pub fn new_with_limiters_and_source(
+        io: T,
+        read: L,
+        write: L,
+        source: S,
+    ) -> AsyncThrottledIo<T, A, S, L> {
+        AsyncThrottledIo {
+            throttle: Throttle::new(read, write, source.now()),
+            io,
+            source,
+            read_gate: Gate::new(),
+            write_gate: Gate::new(),
+        }
+    }
+
+    /// Get the limiter used for reads.
+    #[inline]
+    pub fn read_limiter(&self) -> &L {
+        &self.throttle.read
+    }
+
+    /// Get the limiter used for writes.
+    #[inline]
+    pub fn write_limiter(&self) -> &L {
+        &self.throttle.write
+    }
+
+    /// Get the transfer statistics.
+    ///
+    /// See `ThrottledIo::stats`.
+    #[inline]
+    pub fn stats(&self) -> &Stats {
+        &self.throttle.stats
+    }
+
+    /// Resets the transfer statistics, starting a new measurement.
+    #[inline]
+    pub fn reset_stats(&mut self) {
+        let window = self.throttle.stats.read().window();
+        self.throttle.stats = Stats::new(self.source.now());
+        self.throttle.stats.set_window(window);
+    }
+
+    /// Sets the length of the window used to calculate current rates.
+    ///
+    /// Defaults to one second. Panics if `window` is zero.
+    #[inline]
+    pub fn set_stats_window(&mut self, window: time::Duration) {
+        self.throttle.stats.set_window(window)
+    }
+
+    /// Return the inner reader/writer.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+}
+
+impl<T, A, S, L> fmt::Debug for AsyncThrottledIo<T, A, S, L>
+where
+    T: fmt::Debug,
+    S: fmt::Debug,
+    L: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncThrottledIo")
+            .field("throttle", &self.throttle)
+            .field("io", &self.io)
+            .field("source", &self.source)
+            .field("read_deadline", &self.read_gate.deadline)
+            .field("write_deadline", &self.write_gate.deadline)
+            .finish()
+    }
+}
+
+/// Holds back an operation until a deadline has passed.
+struct Gate<A> {
+    /// Instant at which the gate opens, if closed.
+    deadline: Option<time::Instant>,
+    /// Whether or not the timer has been armed for `deadline`.
+    armed: bool,
+    /// Timer, created on first use.
+    timer: Option<A>,
+}
+
+impl<A> Gate<A> {
+    #[inline]
+    fn new() -> Gate<A> {
+        Gate {
+            deadline: None,
+            armed: false,
+            timer: None,
+        }
+    }
+
+    /// Closes the gate until `deadline`.
+    #[inline]
+    fn close_until(&mut self, deadline: time::Instant) {
+        self.deadline = Some(deadline);
+        self.armed = false;
+    }
+}
+
+impl<A> Gate<A>
+where
+    A: AsyncTimer,
+{
+    /// Polls the gate, returning `Poll::Ready` once it is open.
+    fn poll_open<S: TimeSource>(&mut self, source: &S, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(deadline) = self.deadline {
+            if deadline > source.now() {
+                if !self.armed {
+                    arm(&mut self.timer, deadline);
+                    self.armed = true;
+                }
+
+                if let Some(timer) = self.timer.as_mut() {
+                    ready!(timer.poll_expired(cx));
+                }
+            }
+
+            self.deadline = None;
+        }
+
+        Poll::Ready(())
+    }
+}
+
+impl<T, A, S, L> futures_io::AsyncRead for AsyncThrottledIo<T, A, S, L>
+where
+    T: futures_io::AsyncRead + Unpin,
+    A: AsyncTimer,
+    S: TimeSource + Unpin,
+    L: Limiter + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.read_gate.poll_open(&this.source, cx));
+
+        let bytes_read = ready!(Pin::new(&mut this.io).poll_read(cx, buf))?;
+        let now = this.source.now();
+        this.read_gate
+            .close_until(this.throttle.read(bytes_read, now));
+
+        Poll::Ready(Ok(bytes_read))
+    }
+}
+
+impl<T, A, S, L> futures_io::AsyncWrite for AsyncThrottledIo<T, A, S, L>
+where
+    T: futures_io::AsyncWrite + Unpin,
+    A: AsyncTimer,
+    S: TimeSource + Unpin,
+    L: Limiter + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.write_gate.poll_open(&this.source, cx));
+
+        let bytes_written = ready!(Pin::new(&mut this.io).poll_write(cx, data))?;
+        let now = this.source.now();
+        this.write_gate
+            .close_until(this.throttle.write(bytes_written, now));
+
+        Poll::Ready(Ok(bytes_written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        // the last write is only complete once its delay has passed
+        ready!(this.write_gate.poll_open(&this.source, cx));
+
+        Pin::new(&mut this.io).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        // the last write is only complete once its delay has passed
+        ready!(this.write_gate.poll_open(&this.source, cx));
+
+        Pin::new(&mut this.io).poll_close(cx)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T, A, S, L> tokio::io::AsyncRead for AsyncThrottledIo<T, A, S, L>
+where
+    T: tokio::io::AsyncRead + Unpin,
+    A: AsyncTimer,
+    S: TimeSource + Unpin,
+    L: Limiter + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.read_gate.poll_open(&this.source, cx));
+
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut this.io).poll_read(cx, buf))?;
+        let bytes_read = buf.filled().len() - filled;
+        let now = this.source.now();
+        this.read_gate
+            .close_until(this.throttle.read(bytes_read, now));
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T, A, S, L> tokio::io::AsyncWrite for AsyncThrottledIo<T, A, S, L>
+where
+    T: tokio::io::AsyncWrite + Unpin,
+    A: AsyncTimer,
+    S: TimeSource + Unpin,
+    L: Limiter + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.write_gate.poll_open(&this.source, cx));
+
+        let bytes_written = ready!(Pin::new(&mut this.io).poll_write(cx, data))?;
+        let now = this.source.now();
+        this.write_gate
+            .close_until(this.throttle.write(bytes_written, now));
+
+        Poll::Ready(Ok(bytes_written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        // the last write is only complete once its delay has passed
+        ready!(this.write_gate.poll_open(&this.source, cx));
+
+        Pin::new(&mut this.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        // the last write is only complete once its delay has passed
+        ready!(this.write_gate.poll_open(&this.source, cx));
+
+        Pin::new(&mut this.io).poll_shutdown(cx)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+    use crate::stream::TokioTimer;
+    use crate::time_source::TokioClock;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test(start_paused = true)]
+    async fn throttles_async_reads() {
+        let start = TokioClock.now();
+        let data = [0u8; 300];
+        let mut tio =
+            AsyncThrottledIo::<_, TokioTimer, _>::new_with_source(&data[..], 100, TokioClock);
+
+        let mut buf = [0; 50];
+        for _ in 0..4 {
+            assert_eq!(tio.read(&mut buf).await.unwrap(), 50);
+        }
+
+        // the last read has not been waited for yet
+        assert_eq!(TokioClock.now() - start, time::Duration::from_millis(1500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn throttles_async_writes() {
+        let start = TokioClock.now();
+        let mut tio =
+            AsyncThrottledIo::<_, TokioTimer, _>::new_with_source(Vec::new(), 100, TokioClock);
+
+        tio.write_all(&[1; 20]).await.unwrap();
+        tio.write_all(&[2; 30]).await.unwrap();
+        tio.write_all(&[3; 10]).await.unwrap();
+        assert_eq!(TokioClock.now() - start, time::Duration::from_millis(500));
+
+        assert_eq!(tio.into_inner().len(), 60);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_waits_for_last_write() {
+        let start = TokioClock.now();
+        let mut tio =
+            AsyncThrottledIo::<_, TokioTimer, _>::new_with_source(Vec::new(), 100, TokioClock);
+
+        tio.write_all(&[1; 50]).await.unwrap();
+        assert_eq!(TokioClock.now(), start);
+
+        tio.shutdown().await.unwrap();
+        assert_eq!(TokioClock.now() - start, time::Duration::from_millis(500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn close_waits_for_last_write() {
+        use futures::AsyncWriteExt;
+
+        let start = TokioClock.now();
+        let data = futures::io::Cursor::new(Vec::new());
+        let mut tio = AsyncThrottledIo::<_, TokioTimer, _>::new_with_source(data, 100, TokioClock);
+
+        tio.write_all(&[1; 30]).await.unwrap();
+        tio.close().await.unwrap();
+        assert_eq!(TokioClock.now() - start, time::Duration::from_millis(300));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn implements_futures_io() {
+        use futures::AsyncReadExt;
+
+        let start = TokioClock.now();
+        let data = futures::io::Cursor::new(vec![0u8; 100]);
+        let mut tio = AsyncThrottledIo::<_, TokioTimer, _>::new_with_source(data, 100, TokioClock);
+
+        let mut buf = [0; 60];
+        tio.read_exact(&mut buf).await.unwrap();
+        tio.read_exact(&mut buf[..40]).await.unwrap();
+        assert_eq!(TokioClock.now() - start, time::Duration::from_millis(600));
+    }
+}
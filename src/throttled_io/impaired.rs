@@ -0,0 +1,383 @@
+//! Simulated latency, jitter and faults.
+
+use crate::rng::Rng;
+use crate::time_source::{Sleeper, SystemClock};
+use std::io::{Read, Write};
+use std::{io, time};
+
+/// Impairments applied by `ImpairedIo`.
+///
+/// By default, nothing is impaired. Probabilities are checked independently
+/// on every call to `read` or `write` and should be in `0.0..=1.0`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Impairments {
+    /// Fixed delay before every operation.
+    latency: time::Duration,
+    /// Maximum random delay added on top of `latency`.
+    jitter: time::Duration,
+    /// Probability of an operation transferring fewer bytes than possible.
+    short: f64,
+    /// Probability of an operation failing with `ErrorKind::Interrupted`.
+    interrupted: f64,
+    /// Probability of an operation failing with `ErrorKind::WouldBlock`.
+    would_block: f64,
+    /// Probability of the connection being dropped.
+    disconnect: f64,
+}
+
+impl Impairments {
+    /// Creates a new set of impairments, impairing nothing.
+    #[inline]
+    pub fn new() -> Impairments {
+        Impairments::default()
+    }
+
+    /// Delays every operation by `latency`.
+    #[inline]
+    pub fn with_latency(mut self, latency: time::Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Delays every operation by an additional random duration up to `jitter`.
+    #[inline]
+    pub fn with_jitter(mut self, jitter: time::Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the probability of a short read or write.
+    ///
+    /// A short operation transfers at least one byte, but less than the whole
+    /// buffer.
+    #[inline]
+    pub fn with_short(mut self, probability: f64) -> Self {
+        self.short = probability;
+        self
+    }
+
+    /// Sets the probability of an operation failing with `Interrupted`.
+    #[inline]
+    pub fn with_interrupted(mut self, probability: f64) -> Self {
+        self.interrupted = probability;
+        self
+    }
+
+    /// Sets the probability of an operation failing with `WouldBlock`.
+    #[inline]
+    pub fn with_would_block(mut self, probability: f64) -> Self {
+        self.would_block = probability;
+        self
+    }
+
+    /// Sets the probability of the connection being dropped.
+    ///
+    /// Once dropped, every following operation fails with `ConnectionReset`.
+    #[inline]
+    pub fn with_disconnect(mut self, probability: f64) -> Self {
+        self.disconnect = probability;
+        self
+    }
+
+    /// Get the fixed delay before every operation.
+    #[inline]
+    pub fn latency(&self) -> time::Duration {
+        self.latency
+    }
+
+    /// Get the maximum random delay added on top of the latency.
+    #[inline]
+    pub fn jitter(&self) -> time::Duration {
+        self.jitter
+    }
+
+    /// Get the probability of a short read or write.
+    #[inline]
+    pub fn short(&self) -> f64 {
+        self.short
+    }
+
+    /// Get the probability of an operation failing with `Interrupted`.
+    #[inline]
+    pub fn interrupted(&self) -> f64 {
+        self.interrupted
+    }
+
+    /// Get the probability of an operation failing with `WouldBlock`.
+    #[inline]
+    pub fn would_block(&self) -> f64 {
+        self.would_block
+    }
+
+    /// Get the probability of the connection being dropped.
+    #[inline]
+    pub fn disconnect(&self) -> f64 {
+        self.disconnect
+    }
+}
+
+/// A wrapper that simulates an unreliable link.
+///
+/// Adds latency and jitter to every read and write and randomly injects
+/// short transfers, transient errors or a disconnect, according to the
+/// configured `Impairments`. Faults are drawn from a seeded pseudo random
+/// number generator, so a given seed always produces the same sequence of
+/// faults for the same sequence of calls.
+///
+/// Failed operations never touch the inner IO type. Latency is applied to
+/// each operation individually, before it is forwarded.
+///
+/// Can be combined with `ThrottledIo` to also limit bandwidth:
+///
+/// ```
+/// use std::io::{self, Read};
+/// use std::time;
+/// use ticktock::throttled_io::{ImpairedIo, Impairments, ThrottledIo};
+///
+/// let impairments = Impairments::new()
+///     .with_latency(time::Duration::from_millis(1))
+///     .with_jitter(time::Duration::from_millis(1))
+///     .with_interrupted(0.1);
+/// let mut link = ImpairedIo::new(ThrottledIo::new(io::repeat(0), 1_000_000), impairments, 42);
+///
+/// let mut buf = [0; 100];
+/// match link.read(&mut buf) {
+///     Ok(n) => assert!(n <= 100),
+///     Err(e) => assert_eq!(e.kind(), io::ErrorKind::Interrupted),
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ImpairedIo<T, S = SystemClock> {
+    /// Inner IO type.
+    io: T,
+    /// Configured impairments.
+    impairments: Impairments,
+    /// Source of randomness for faults and jitter.
+    rng: Rng,
+    /// Whether or not a disconnect has been simulated.
+    disconnected: bool,
+    /// Time source used for sleeping.
+    source: S,
+}
+
+impl<T> ImpairedIo<T> {
+    /// Creates a new impaired wrapper, seeding the random number generator
+    /// with `seed`.
+    #[inline]
+    pub fn new(io: T, impairments: Impairments, seed: u64) -> ImpairedIo<T> {
+        ImpairedIo::new_with_source(io, impairments, seed, SystemClock)
+    }
+}
+
+impl<T, S> ImpairedIo<T, S>
+where
+    S: Sleeper,
+{
+    /// Creates a new impaired wrapper using a custom time source.
+    #[inline]
+    pub fn new_with_source(io: T, impairments: Impairments, seed: u64, source: S) -> Self {
+        ImpairedIo {
+            io,
+            impairments,
+            rng: Rng::new(seed),
+            disconnected: false,
+            source,
+        }
+    }
+
+    /// Get the configured impairments.
+    #[inline]
+    pub fn impairments(&self) -> &Impairments {
+        &self.impairments
+    }
+
+    /// Changes the impairments, effective with the next operation.
+    #[inline]
+    pub fn set_impairments(&mut self, impairments: Impairments) {
+        self.impairments = impairments;
+    }
+
+    /// Whether or not a disconnect has been simulated.
+    #[inline]
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected
+    }
+
+    /// Restores the connection after a simulated disconnect.
+    #[inline]
+    pub fn reconnect(&mut self) {
+        self.disconnected = false;
+    }
+
+    /// Unwraps the inner IO type.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+
+    /// Applies impairments to an operation on a buffer of `len` bytes.
+    ///
+    /// Returns the number of bytes to pass on to the inner IO type.
+    fn impair(&mut self, len: usize) -> io::Result<usize> {
+        if self.disconnected {
+            return Err(io::ErrorKind::ConnectionReset.into());
+        }
+
+        if self.rng.chance(self.impairments.disconnect) {
+            self.disconnected = true;
+            return Err(io::ErrorKind::ConnectionReset.into());
+        }
+
+        if self.rng.chance(self.impairments.interrupted) {
+            return Err(io::ErrorKind::Interrupted.into());
+        }
+
+        if self.rng.chance(self.impairments.would_block) {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let delay = self.impairments.latency
+            + self
+                .rng
+                .duration_between(time::Duration::from_secs(0), self.impairments.jitter);
+        if delay > time::Duration::from_secs(0) {
+            self.source.sleep(delay);
+        }
+
+        if len > 1 && self.rng.chance(self.impairments.short) {
+            Ok(self.rng.between(1, len as u64 - 1) as usize)
+        } else {
+            Ok(len)
+        }
+    }
+}
+
+impl<T, S> Read for ImpairedIo<T, S>
+where
+    T: Read,
+    S: Sleeper,
+{
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.impair(buf.len())?;
+        self.io.read(&mut buf[..len])
+    }
+}
+
+impl<T, S> Write for ImpairedIo<T, S>
+where
+    T: Write,
+    S: Sleeper,
+{
+    #[inline]
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let len = self.impair(data.len())?;
+        self.io.write(&data[..len])
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        if self.disconnected {
+            return Err(io::ErrorKind::ConnectionReset.into());
+        }
+
+        self.io.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time_source::MockClock;
+
+    #[test]
+    fn adds_latency_and_jitter() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let impairments = Impairments::new()
+            .with_latency(time::Duration::from_millis(50))
+            .with_jitter(time::Duration::from_millis(10));
+        let mut io = ImpairedIo::new_with_source(io::repeat(0), impairments, 1, mock.clone());
+
+        assert_eq!(io.impairments().latency(), time::Duration::from_millis(50));
+        assert_eq!(io.impairments().jitter(), time::Duration::from_millis(10));
+        assert_eq!(io.impairments().disconnect(), 0.0);
+
+        let mut buf = [0; 10];
+        for i in 1..=10 {
+            assert_eq!(io.read(&mut buf).unwrap(), 10);
+
+            let elapsed = mock.now() - start;
+            assert!(elapsed >= time::Duration::from_millis(50 * i));
+            assert!(elapsed <= time::Duration::from_millis(60 * i));
+        }
+    }
+
+    #[test]
+    fn injects_errors() {
+        let mock = MockClock::new();
+        let mut io = ImpairedIo::new_with_source(
+            Vec::new(),
+            Impairments::new().with_would_block(1.0),
+            1,
+            mock.clone(),
+        );
+
+        let err = io.write(&[1, 2, 3]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert!(io.io.is_empty());
+
+        io.set_impairments(Impairments::new().with_interrupted(1.0));
+        let err = io.write(&[1, 2, 3]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+
+        // short writes still get everything through eventually
+        io.set_impairments(Impairments::new().with_short(1.0));
+        io.write_all(&[1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(io.into_inner(), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn disconnects_permanently() {
+        let mut io = ImpairedIo::new_with_source(
+            Vec::new(),
+            Impairments::new().with_disconnect(1.0),
+            1,
+            MockClock::new(),
+        );
+
+        assert!(io.write(&[1]).is_err());
+        assert!(io.is_disconnected());
+
+        io.set_impairments(Impairments::new());
+        let err = io.write(&[1]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+
+        io.reconnect();
+        assert_eq!(io.write(&[1]).unwrap(), 1);
+    }
+
+    #[test]
+    fn same_seed_same_faults() {
+        let impairments = Impairments::new()
+            .with_short(0.3)
+            .with_interrupted(0.2)
+            .with_would_block(0.2)
+            .with_jitter(time::Duration::from_millis(5));
+
+        let run = |seed| {
+            let mock = MockClock::new();
+            let start = mock.now();
+            let mut io =
+                ImpairedIo::new_with_source(io::repeat(0), impairments.clone(), seed, mock.clone());
+
+            let results: Vec<_> = (0..50)
+                .map(|_| io.read(&mut [0; 16]).map_err(|e| e.kind()))
+                .collect();
+            (results, mock.now() - start)
+        };
+
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+}
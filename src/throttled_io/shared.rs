@@ -0,0 +1,395 @@
+//! Bandwidth budgets shared between multiple limiters.
+
+use super::{Limiter, RateLimiter};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time;
+
+/// Default time after which a handle no longer counts as active.
+const DEFAULT_IDLE_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+/// Allocation of a shared bandwidth budget between handles.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Allocation {
+    /// All handles draw from the same bucket, first come, first served.
+    Shared,
+    /// Each handle gets a share of the rate and burst, proportional to its
+    /// weight.
+    ///
+    /// The budget is only divided between recently active handles, i.e. ones
+    /// that transferred something within the idle timeout (see
+    /// `SharedLimiter::with_idle_timeout`). Bandwidth left unused by idle
+    /// handles is redistributed among the active ones, a handle that never
+    /// transfers anything does not reduce the shares of the others at all.
+    Weighted,
+}
+
+/// A handle to a bandwidth budget shared between multiple users.
+///
+/// All handles derived from the same `SharedLimiter` draw from a single
+/// `RateLimiter`, allowing multiple `ThrottledIo` instances to simulate a
+/// single constrained link. Handles are cheap to create, can be sent to other
+/// threads, and leave the budget when dropped.
+///
+/// Cloning a handle creates a new handle with the same weight.
+///
+/// `ThrottledIo::new_with_limiter` clones the handle it is given for reads
+/// and writes, so a stream counts as two handles of the budget. With
+/// `Allocation::Weighted`, a stream reading and writing at the same time
+/// therefore gets two shares; a direction left unused goes idle and does not
+/// count. Pass handles of the intended weights to
+/// `ThrottledIo::new_with_limiters` to control each direction's share.
+///
+/// ```
+/// use std::io::{self, Write};
+/// use ticktock::throttled_io::{Allocation, RateLimiter, SharedLimiter, ThrottledIo};
+///
+/// // a single 1 mb/s uplink, with `b` getting twice the bandwidth of `a`
+/// let uplink = SharedLimiter::new(RateLimiter::new(1_000_000, 0), Allocation::Weighted);
+///
+/// let mut a = ThrottledIo::new_with_limiter(io::sink(), uplink.clone());
+/// let mut b = ThrottledIo::new_with_limiter(io::sink(), uplink.handle(2));
+///
+/// a.write_all(&[0; 1000]).unwrap();
+/// b.write_all(&[0; 1000]).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SharedLimiter {
+    /// State shared between all handles.
+    state: Arc<Mutex<State>>,
+    /// Identifies this handle's share.
+    id: u64,
+}
+
+/// Shared state of all handles.
+#[derive(Debug)]
+struct State {
+    /// Limiter for the whole budget, used directly with `Allocation::Shared`.
+    limiter: RateLimiter,
+    /// How the budget is split between handles.
+    allocation: Allocation,
+    /// Shares of all live handles.
+    shares: HashMap<u64, Share>,
+    /// Id of the next handle created.
+    next_id: u64,
+    /// Time after which a handle no longer counts as active.
+    idle_timeout: time::Duration,
+    /// Whether or not shares need to be recalculated.
+    rebalance: bool,
+}
+
+/// A single handle's share of the budget.
+#[derive(Debug)]
+struct Share {
+    /// Weight relative to the other shares.
+    weight: u32,
+    /// Whether or not the handle is part of the current split.
+    active: bool,
+    /// Instant the last transfer of the handle completes, if any.
+    busy_until: Option<time::Instant>,
+    /// Limiter for this share, used with `Allocation::Weighted`.
+    limiter: RateLimiter,
+}
+
+impl SharedLimiter {
+    /// Creates a new shared budget, returning a first handle with weight 1.
+    #[inline]
+    pub fn new(limiter: RateLimiter, allocation: Allocation) -> SharedLimiter {
+        let state = State {
+            limiter,
+            allocation,
+            shares: HashMap::new(),
+            next_id: 0,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            rebalance: false,
+        };
+
+        SharedLimiter::join(Arc::new(Mutex::new(state)), 1)
+    }
+
+    /// Creates a new handle to the same budget, with the specified weight.
+    ///
+    /// Panics if `weight` is zero.
+    #[inline]
+    pub fn handle(&self, weight: u32) -> SharedLimiter {
+        SharedLimiter::join(self.state.clone(), weight)
+    }
+
+    /// Sets the time after which a handle no longer counts as active
+    ///
+    /// Only affects `Allocation::Weighted`, applies to all handles of the
+    /// budget. Defaults to one second.
+    #[inline]
+    pub fn with_idle_timeout(self, idle_timeout: time::Duration) -> SharedLimiter {
+        self.lock().idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Get the time after which a handle no longer counts as active.
+    #[inline]
+    pub fn idle_timeout(&self) -> time::Duration {
+        self.lock().idle_timeout
+    }
+
+    /// Get the weight of this handle.
+    #[inline]
+    pub fn weight(&self) -> u32 {
+        self.lock().shares[&self.id].weight
+    }
+
+    /// Get the total rate of the budget in bytes per second, `None` if
+    /// unlimited.
+    #[inline]
+    pub fn bytes_per_second(&self) -> Option<u32> {
+        self.lock().limiter.bytes_per_second()
+    }
+
+    /// Changes the total rate of the budget, `None` meaning unlimited.
+    ///
+    /// Affects all handles, see `RateLimiter::set_rate`.
+    pub fn set_rate(&self, bytes_per_second: Option<u32>, now: time::Instant) {
+        let mut state = self.lock();
+
+        state.limiter.set_rate(bytes_per_second, now);
+        state.rebalance(now);
+    }
+
+    /// Registers a new handle with `state`.
+    fn join(state: Arc<Mutex<State>>, weight: u32) -> SharedLimiter {
+        assert!(weight > 0, "weight must not be zero");
+
+        let id = {
+            let mut guard = state.lock().expect("shared limiter poisoned");
+            let id = guard.next_id;
+            guard.next_id += 1;
+
+            // the share's limiter is configured once the handle becomes active
+            let limiter = RateLimiter::unlimited(0, guard.limiter.paid_until);
+            guard.shares.insert(
+                id,
+                Share {
+                    weight,
+                    active: false,
+                    busy_until: None,
+                    limiter,
+                },
+            );
+            id
+        };
+
+        SharedLimiter { state, id }
+    }
+
+    #[inline]
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("shared limiter poisoned")
+    }
+}
+
+impl State {
+    /// Updates which shares count as active, returning whether any changed.
+    fn update_activity(&mut self, now: time::Instant) -> bool {
+        let idle_timeout = self.idle_timeout;
+        let mut changed = false;
+
+        for share in self.shares.values_mut() {
+            // an idle timeout too large to add never expires
+            let active = matches!(
+                share.busy_until,
+                Some(busy_until) if busy_until
+                    .checked_add(idle_timeout)
+                    .is_none_or(|idle_at| idle_at >= now)
+            );
+
+            changed |= active != share.active;
+            share.active = active;
+        }
+
+        changed
+    }
+
+    /// Divides the budget between all active shares, according to weight.
+    fn rebalance(&mut self, now: time::Instant) {
+        let total_weight: u64 = self
+            .shares
+            .values()
+            .filter(|share| share.active)
+            .map(|share| share.weight as u64)
+            .sum();
+
+        let rate = self.limiter.bytes_per_second();
+        let burst = self.limiter.burst() as u64;
+
+        for share in self.shares.values_mut().filter(|share| share.active) {
+            let weight = share.weight as u64;
+            let share_rate = rate.map(|rate| (rate as u64 * weight / total_weight).max(1) as u32);
+
+            share.limiter.set_rate(share_rate, now);
+            share.limiter.burst = (burst * weight / total_weight) as u32;
+        }
+
+        self.rebalance = false;
+    }
+}
+
+impl Clone for SharedLimiter {
+    #[inline]
+    fn clone(&self) -> Self {
+        self.handle(self.weight())
+    }
+}
+
+impl Drop for SharedLimiter {
+    fn drop(&mut self) {
+        // do not panic while dropping, a poisoned state is of no use anyway
+        if let Ok(mut state) = self.state.lock() {
+            if let Some(share) = state.shares.remove(&self.id) {
+                state.rebalance |= share.active;
+            }
+        }
+    }
+}
+
+impl Limiter for SharedLimiter {
+    fn acquire(&mut self, bytes: u64, now: time::Instant) -> time::Duration {
+        let mut state = self.lock();
+
+        match state.allocation {
+            Allocation::Shared => state.limiter.acquire(bytes, now),
+            Allocation::Weighted => {
+                let share = state
+                    .shares
+                    .get_mut(&self.id)
+                    .expect("share of live handle missing");
+                share.busy_until = Some(share.busy_until.map_or(now, |t| t.max(now)));
+
+                if state.update_activity(now) || state.rebalance {
+                    state.rebalance(now);
+                }
+
+                let share = state
+                    .shares
+                    .get_mut(&self.id)
+                    .expect("share of live handle missing");
+                let delay = share.limiter.acquire(bytes, now);
+                share.busy_until = Some(now + delay);
+
+                delay
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::throttled_io::ThrottledIo;
+    use crate::time_source::MockClock;
+    use std::io::Write;
+
+    #[test]
+    fn shares_single_bucket() {
+        let mock = MockClock::new();
+        let start = mock.now();
+        let uplink = SharedLimiter::new(
+            RateLimiter::new_with_start_time(100, 0, start),
+            Allocation::Shared,
+        );
+
+        let mut a =
+            ThrottledIo::new_with_limiter_and_source(Vec::new(), uplink.clone(), mock.clone());
+        let mut b = ThrottledIo::new_with_limiter_and_source(Vec::new(), uplink, mock.clone());
+
+        a.write_all(&[0; 100]).unwrap();
+        assert_eq!(mock.now() - start, time::Duration::from_secs(1));
+
+        b.write_all(&[0; 100]).unwrap();
+        assert_eq!(mock.now() - start, time::Duration::from_secs(2));
+    }
+
+    #[test]
+    fn weighted_shares() {
+        let now = time::Instant::now();
+        let mut a = SharedLimiter::new(
+            RateLimiter::new_with_start_time(400, 0, now),
+            Allocation::Weighted,
+        );
+        let mut b = a.handle(3);
+        let c = a.handle(4);
+
+        a.acquire(0, now);
+        b.acquire(0, now);
+
+        // c is never used, so the budget is split 1:3
+        assert_eq!(a.acquire(100, now), time::Duration::from_secs(1));
+        assert_eq!(b.acquire(300, now), time::Duration::from_secs(1));
+        assert_eq!(c.weight(), 4);
+
+        // once b is gone, a gets the full rate
+        drop(b);
+        let later = now + time::Duration::from_secs(1);
+        assert_eq!(a.acquire(400, later), time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn idle_shares_are_redistributed() {
+        let now = time::Instant::now();
+        let secs = |n| now + time::Duration::from_secs(n);
+        let mut a = SharedLimiter::new(
+            RateLimiter::new_with_start_time(400, 0, now),
+            Allocation::Weighted,
+        )
+        .with_idle_timeout(time::Duration::from_secs(2));
+        let mut b = a.handle(3);
+
+        a.acquire(0, now);
+        assert_eq!(b.acquire(300, now), time::Duration::from_secs(1));
+        assert_eq!(a.acquire(100, now), time::Duration::from_secs(1));
+
+        // b is still busy or recently active, a keeps its quarter
+        assert_eq!(a.acquire(100, secs(3)), time::Duration::from_secs(1));
+
+        // b went idle, a gets the full rate
+        assert_eq!(a.acquire(400, secs(4)), time::Duration::from_secs(1));
+
+        // until b becomes active again
+        assert_eq!(b.acquire(300, secs(5)), time::Duration::from_secs(1));
+        assert_eq!(a.idle_timeout(), time::Duration::from_secs(2));
+    }
+
+    #[test]
+    fn unlimited_idle_timeout() {
+        let now = time::Instant::now();
+        let mut a = SharedLimiter::new(
+            RateLimiter::new_with_start_time(400, 0, now),
+            Allocation::Weighted,
+        )
+        .with_idle_timeout(time::Duration::MAX);
+        let mut b = a.handle(3);
+
+        a.acquire(0, now);
+        b.acquire(300, now);
+
+        // b never goes idle, a keeps its quarter
+        let later = now + time::Duration::from_secs(3600);
+        assert_eq!(a.acquire(100, later), time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn rate_changes_apply_to_all_handles() {
+        let now = time::Instant::now();
+        let mut a = SharedLimiter::new(
+            RateLimiter::new_with_start_time(100, 0, now),
+            Allocation::Weighted,
+        );
+        let mut b = a.clone();
+
+        a.acquire(0, now);
+        b.acquire(0, now);
+        b.set_rate(Some(1000), now);
+
+        assert_eq!(a.bytes_per_second(), Some(1000));
+        assert_eq!(a.acquire(500, now), time::Duration::from_secs(1));
+        assert_eq!(b.acquire(500, now), time::Duration::from_secs(1));
+    }
+}
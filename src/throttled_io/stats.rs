@@ -0,0 +1,244 @@
+//! Transfer statistics.
+
+use super::NS_PER_SECOND;
+use std::collections::VecDeque;
+use std::time;
+
+/// Default length of the window used for `TransferStats::current_rate`.
+const DEFAULT_WINDOW: time::Duration = time::Duration::from_secs(1);
+
+/// Statistics of a throttled reader/writer.
+///
+/// Keeps track of reads and writes separately, see `TransferStats`.
+///
+/// ```
+/// use std::io::{self, Read};
+/// use std::time;
+/// use ticktock::throttled_io::ThrottledIo;
+/// use ticktock::time_source::MockClock;
+///
+/// let mock = MockClock::new();
+/// let mut tio = ThrottledIo::new_with_source(io::repeat(0), 1000, mock.clone());
+///
+/// let mut buf = [0; 100];
+/// for _ in 0..10 {
+///     tio.read_exact(&mut buf).unwrap();
+/// }
+///
+/// let reads = tio.stats().read();
+/// assert_eq!(reads.total_bytes(), 1000);
+/// assert_eq!(reads.time_sleeping(), time::Duration::from_secs(1));
+/// assert_eq!(reads.average_rate(mock.now()), 1000.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Stats {
+    /// Statistics of reads.
+    read: TransferStats,
+    /// Statistics of writes.
+    write: TransferStats,
+}
+
+/// Statistics of transfers in one direction.
+///
+/// Rates are in bytes per second. A transfer is counted as completed once the
+/// limiter allows it to be, i.e. at the end of the pause following it.
+#[derive(Clone, Debug)]
+pub struct TransferStats {
+    /// Start of the measurement.
+    started_at: time::Instant,
+    /// Total number of bytes transferred.
+    total_bytes: u64,
+    /// Total time spent waiting for the limiter.
+    time_sleeping: time::Duration,
+    /// Length of the window for the current rate.
+    window: time::Duration,
+    /// Completed transfers within the window, as `(completed at, bytes)`.
+    samples: VecDeque<(time::Instant, u64)>,
+}
+
+impl Stats {
+    /// Creates new, empty statistics.
+    #[inline]
+    pub(crate) fn new(now: time::Instant) -> Stats {
+        Stats {
+            read: TransferStats::new(now),
+            write: TransferStats::new(now),
+        }
+    }
+
+    /// Get the statistics of reads.
+    #[inline]
+    pub fn read(&self) -> &TransferStats {
+        &self.read
+    }
+
+    /// Get the statistics of writes.
+    #[inline]
+    pub fn write(&self) -> &TransferStats {
+        &self.write
+    }
+
+    #[inline]
+    pub(crate) fn read_mut(&mut self) -> &mut TransferStats {
+        &mut self.read
+    }
+
+    #[inline]
+    pub(crate) fn write_mut(&mut self) -> &mut TransferStats {
+        &mut self.write
+    }
+
+    /// Sets the window length used to calculate current rates.
+    ///
+    /// Panics if `window` is zero.
+    #[inline]
+    pub(crate) fn set_window(&mut self, window: time::Duration) {
+        assert!(
+            window > time::Duration::from_secs(0),
+            "window must not be zero"
+        );
+
+        self.read.window = window;
+        self.write.window = window;
+    }
+}
+
+impl TransferStats {
+    #[inline]
+    fn new(now: time::Instant) -> TransferStats {
+        TransferStats {
+            started_at: now,
+            total_bytes: 0,
+            time_sleeping: time::Duration::from_secs(0),
+            window: DEFAULT_WINDOW,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Get the instant at which measurement started.
+    #[inline]
+    pub fn started_at(&self) -> time::Instant {
+        self.started_at
+    }
+
+    /// Total number of bytes transferred.
+    #[inline]
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    /// Total time spent waiting for the limiter.
+    #[inline]
+    pub fn time_sleeping(&self) -> time::Duration {
+        self.time_sleeping
+    }
+
+    /// Time passed since measurement started.
+    #[inline]
+    pub fn elapsed(&self, now: time::Instant) -> time::Duration {
+        now.saturating_duration_since(self.started_at)
+    }
+
+    /// Average rate since measurement started.
+    ///
+    /// Returns 0 if no time has passed yet.
+    #[inline]
+    pub fn average_rate(&self, now: time::Instant) -> f64 {
+        rate(self.total_bytes, self.elapsed(now))
+    }
+
+    /// Length of the window used for the current rate.
+    #[inline]
+    pub fn window(&self) -> time::Duration {
+        self.window
+    }
+
+    /// Rate over the last `window` before `now`.
+    ///
+    /// Shortly after measurement started, the rate is calculated over the
+    /// elapsed time instead.
+    pub fn current_rate(&self, now: time::Instant) -> f64 {
+        let window_start = now.checked_sub(self.window).unwrap_or(self.started_at);
+        let bytes = self
+            .samples
+            .iter()
+            .filter(|&&(completed_at, _)| completed_at > window_start && completed_at <= now)
+            .map(|&(_, bytes)| bytes)
+            .sum();
+
+        rate(bytes, self.window.min(self.elapsed(now)))
+    }
+
+    /// Records a transfer of `bytes` started at `now` and completing at
+    /// `completed_at`.
+    pub(crate) fn record(&mut self, bytes: u64, now: time::Instant, completed_at: time::Instant) {
+        self.total_bytes += bytes;
+        self.time_sleeping += completed_at.saturating_duration_since(now);
+
+        if let Some(cutoff) = now.checked_sub(self.window) {
+            while matches!(self.samples.front(), Some(&(completed_at, _)) if completed_at <= cutoff)
+            {
+                self.samples.pop_front();
+            }
+        }
+
+        if bytes > 0 {
+            self.samples.push_back((completed_at, bytes));
+        }
+    }
+}
+
+/// Calculates a rate in bytes per second.
+#[inline]
+fn rate(bytes: u64, duration: time::Duration) -> f64 {
+    let nanos = duration.as_nanos();
+
+    if nanos == 0 {
+        return 0.0;
+    }
+
+    bytes as f64 * NS_PER_SECOND as f64 / nanos as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_rate_uses_window() {
+        let start = time::Instant::now();
+        let mut stats = TransferStats::new(start);
+        let secs = |s| start + time::Duration::from_secs(s);
+
+        // 100 b/s for 10 seconds, then 500 b/s
+        for s in 0..10 {
+            stats.record(100, secs(s), secs(s + 1));
+        }
+        assert_eq!(stats.current_rate(secs(10)), 100.0);
+
+        for s in 10..12 {
+            stats.record(500, secs(s), secs(s + 1));
+        }
+
+        assert_eq!(stats.total_bytes(), 2000);
+        assert_eq!(stats.time_sleeping(), time::Duration::from_secs(12));
+        assert_eq!(stats.average_rate(secs(12)), 2000.0 / 12.0);
+        assert_eq!(stats.current_rate(secs(12)), 500.0);
+
+        // old samples are discarded
+        assert!(stats.samples.len() <= 3);
+    }
+
+    #[test]
+    fn rates_before_time_passes() {
+        let start = time::Instant::now();
+        let mut stats = TransferStats::new(start);
+
+        assert_eq!(stats.average_rate(start), 0.0);
+        assert_eq!(stats.current_rate(start), 0.0);
+
+        let later = start + time::Duration::from_millis(500);
+        stats.record(100, start, later);
+        assert_eq!(stats.current_rate(later), 200.0);
+    }
+}
@@ -0,0 +1,235 @@
+//! Time sources
+//!
+//! Every component that needs to know the current time or has to wait does so
+//! through the `TimeSource` and `Sleeper` traits. By default, the system clock
+//! is used, but a manually advanced `MockClock` can be substituted, e.g. to
+//! make tests of frame loops or rate limiting run instantly and
+//! deterministically:
+//!
+//! ```
+//! use std::time;
+//! use ticktock::time_source::MockClock;
+//! use ticktock::Clock;
+//!
+//! let mock = MockClock::new();
+//! let start = mock.now();
+//! let clock = Clock::new_with_source(time::Duration::from_secs(60), mock.clone());
+//!
+//! // returns immediately, as sleeping simply advances the mock clock
+//! let (tick, now) = clock.wait_until_tick();
+//!
+//! assert_eq!(tick, 1);
+//! assert_eq!(now - start, time::Duration::from_secs(60));
+//! assert_eq!(mock.now(), now);
+//! ```
+
+use std::sync::{Arc, Mutex};
+use std::{hint, thread, time};
+
+/// A source of the current time.
+pub trait TimeSource {
+    /// Returns the current instant.
+    fn now(&self) -> time::Instant;
+}
+
+/// A time source that is able to wait.
+pub trait Sleeper: TimeSource {
+    /// Blocks for the given duration.
+    fn sleep(&self, duration: time::Duration);
+
+    /// Blocks until `deadline` has been reached.
+    ///
+    /// Returns immediately if `deadline` is not in the future.
+    #[inline]
+    fn sleep_until(&self, deadline: time::Instant) {
+        let now = self.now();
+
+        if deadline > now {
+            self.sleep(deadline - now);
+        }
+    }
+
+    /// Busy-waits until `deadline` has been reached.
+    ///
+    /// Precise, but keeps the CPU busy. Time sources that do not pass time
+    /// on their own must override this.
+    #[inline]
+    fn spin_until(&self, deadline: time::Instant) {
+        while self.now() < deadline {
+            hint::spin_loop();
+        }
+    }
+
+    /// Yields to other threads until `deadline` has been reached.
+    ///
+    /// Time sources that do not pass time on their own must override this.
+    #[inline]
+    fn yield_until(&self, deadline: time::Instant) {
+        while self.now() < deadline {
+            thread::yield_now();
+        }
+    }
+}
+
+/// The system clock.
+///
+/// Uses `Instant::now()` and `thread::sleep`, this is the default time source.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl TimeSource for SystemClock {
+    #[inline]
+    fn now(&self) -> time::Instant {
+        time::Instant::now()
+    }
+}
+
+impl Sleeper for SystemClock {
+    #[inline]
+    fn sleep(&self, duration: time::Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// A manually advanced clock.
+///
+/// Time only passes when `advance` or `set` are called, or when something
+/// sleeps on the clock, which returns immediately after moving time forward by
+/// the requested duration.
+///
+/// Clones share the same underlying time, so a clone can be handed to a
+/// `Clock` or `ThrottledIo` while the original is kept around to inspect or
+/// manipulate time.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    /// Current time, shared between all clones.
+    now: Arc<Mutex<time::Instant>>,
+}
+
+impl MockClock {
+    /// Creates a new mock clock, starting at the current system time.
+    #[inline]
+    pub fn new() -> MockClock {
+        MockClock::starting_at(time::Instant::now())
+    }
+
+    /// Creates a new mock clock, starting at `start`.
+    #[inline]
+    pub fn starting_at(start: time::Instant) -> MockClock {
+        MockClock {
+            now: Arc::new(Mutex::new(start)),
+        }
+    }
+
+    /// Returns the current mock time.
+    ///
+    /// Shortcut for `TimeSource::now`, to avoid having to import the trait.
+    #[inline]
+    pub fn now(&self) -> time::Instant {
+        *self.now.lock().expect("mock clock poisoned")
+    }
+
+    /// Moves time forward by `duration`.
+    #[inline]
+    pub fn advance(&self, duration: time::Duration) {
+        *self.now.lock().expect("mock clock poisoned") += duration;
+    }
+
+    /// Sets the current time.
+    ///
+    /// Note: Setting the time into the past will likely cause other components
+    /// relying on the clock to panic.
+    #[inline]
+    pub fn set(&self, now: time::Instant) {
+        *self.now.lock().expect("mock clock poisoned") = now;
+    }
+}
+
+impl Default for MockClock {
+    #[inline]
+    fn default() -> Self {
+        MockClock::new()
+    }
+}
+
+impl TimeSource for MockClock {
+    #[inline]
+    fn now(&self) -> time::Instant {
+        MockClock::now(self)
+    }
+}
+
+impl Sleeper for MockClock {
+    #[inline]
+    fn sleep(&self, duration: time::Duration) {
+        self.advance(duration)
+    }
+
+    #[inline]
+    fn spin_until(&self, deadline: time::Instant) {
+        self.sleep_until(deadline)
+    }
+
+    #[inline]
+    fn yield_until(&self, deadline: time::Instant) {
+        self.sleep_until(deadline)
+    }
+}
+
+/// The clock of the tokio runtime.
+///
+/// Reads the current time from `tokio::time::Instant`, which can be paused
+/// and advanced in tests. Only implements `TimeSource`, as sleeping is done
+/// asynchronously, see the `stream` module.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioClock;
+
+#[cfg(feature = "tokio")]
+impl TimeSource for TokioClock {
+    #[inline]
+    fn now(&self) -> time::Instant {
+        tokio::time::Instant::now().into_std()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_clock_only_moves_when_told() {
+        let mock = MockClock::new();
+        let start = mock.now();
+
+        assert_eq!(mock.now(), start);
+
+        mock.advance(time::Duration::from_millis(5));
+        assert_eq!(mock.now() - start, time::Duration::from_millis(5));
+
+        mock.set(start + time::Duration::from_secs(1));
+        assert_eq!(mock.now() - start, time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn mock_clock_clones_share_time() {
+        let mock = MockClock::new();
+        let other = mock.clone();
+
+        other.sleep(time::Duration::from_secs(3));
+        assert_eq!(mock.now(), other.now());
+    }
+
+    #[test]
+    fn sleep_until_ignores_past_deadlines() {
+        let mock = MockClock::new();
+        let start = mock.now();
+
+        mock.advance(time::Duration::from_secs(1));
+        mock.sleep_until(start);
+        assert_eq!(mock.now() - start, time::Duration::from_secs(1));
+
+        mock.sleep_until(start + time::Duration::from_secs(2));
+        assert_eq!(mock.now() - start, time::Duration::from_secs(2));
+    }
+}
@@ -31,8 +31,34 @@
 //!     }
 //! }
 //! ```
+//!
+//! When dealing with a large number of timers, a `TimerSet` can be used to
+//! only process those that are due.
+
+use crate::clock::duration_multiple;
+use crate::schedule::Schedule;
+use crate::time_source::TimeSource;
+use std::convert::TryFrom;
+use std::{fmt, time};
+
+mod set;
 
-use std::time;
+pub use self::set::{TimerHandle, TimerSet};
+
+/// A timer with a boxed function
+///
+/// All boxed timers with the same value and result types share a single
+/// type, allowing timers with different functions to be stored together,
+/// e.g. in a `Vec` or a struct field. Created using `Timer::boxed`. The
+/// function must be `Send`, so boxed timers can be moved across threads.
+pub type BoxedTimer<V, R> = Timer<Box<dyn FnMut(time::Duration, &mut V) -> R + Send>, V, R>;
+
+/// A timer with a boxed function that need not be `Send`
+///
+/// Like `BoxedTimer`, but also allows functions capturing e.g. `Rc` or
+/// `RefCell`, at the cost of the timer not being `Send`. Created using
+/// `Timer::boxed_local`.
+pub type LocalBoxedTimer<V, R> = Timer<Box<dyn FnMut(time::Duration, &mut V) -> R>, V, R>;
 
 /// A timer builder
 ///
@@ -40,17 +66,22 @@ use std::time;
 #[derive(Debug)]
 pub struct TimerBuilder<F, V, R>
 where
-    F: Fn(time::Duration, &mut V) -> R,
+    F: FnMut(time::Duration, &mut V) -> R,
 {
     func: F,
     initial: V,
     interval: Option<time::Duration>,
+    schedule: Option<DynSchedule>,
     repeat: bool,
+    catch_up: bool,
 }
 
+/// A boxed schedule
+struct DynSchedule(Box<dyn Schedule + Send>);
+
 impl<F, V, R> TimerBuilder<F, V, R>
 where
-    F: Fn(time::Duration, &mut V) -> R,
+    F: FnMut(time::Duration, &mut V) -> R,
 {
     #[inline]
     fn new(func: F, initial: V) -> TimerBuilder<F, V, R> {
@@ -58,7 +89,9 @@ where
             func,
             initial,
             interval: None,
+            schedule: None,
             repeat: true,
+            catch_up: false,
         }
     }
 
@@ -69,6 +102,7 @@ where
     #[inline]
     pub fn every(mut self, interval: time::Duration) -> Self {
         self.interval = Some(interval);
+        self.schedule = None;
         self.repeat = true;
         self
     }
@@ -77,43 +111,108 @@ where
     #[inline]
     pub fn once(mut self, delay: time::Duration) -> Self {
         self.interval = Some(delay);
+        self.schedule = None;
         self.repeat = false;
         self
     }
 
+    /// Execute according to a schedule
+    ///
+    /// The timer fires at every deadline of the schedule and expires once the
+    /// schedule ends. The schedule must be `Send`, so the timer stays `Send`.
+    /// See the `schedule` module.
+    #[inline]
+    pub fn schedule<S>(mut self, schedule: S) -> Self
+    where
+        S: Schedule + Send + 'static,
+    {
+        self.interval = None;
+        self.schedule = Some(DynSchedule(Box::new(schedule)));
+        self.repeat = true;
+        self
+    }
+
+    /// Execute once for every elapsed interval
+    ///
+    /// By default, a timer that is updated late only executes once, passing
+    /// in the whole elapsed time. In catch-up mode, the function is instead
+    /// executed once for every interval that elapsed, each time with the
+    /// interval as elapsed time, and the last result is returned.
+    #[inline]
+    pub fn catch_up(mut self) -> Self {
+        self.catch_up = true;
+        self
+    }
+
     /// Start the timer
     ///
     /// Starting means recording the passed in `now` as the timer's start time
     /// (and basis for calculations).
-    pub fn start(self, now: time::Instant) -> Timer<F, V, R> {
-        let interval = self.interval.expect("no timing set");
-        let next_tick = now + interval;
+    pub fn start(mut self, now: time::Instant) -> Timer<F, V, R> {
+        let (interval, next_tick, expired) = match self.schedule.as_mut() {
+            Some(schedule) => match schedule.0.next_after(now) {
+                Some(next_tick) => (next_tick.saturating_duration_since(now), next_tick, false),
+                None => (time::Duration::from_secs(0), now, true),
+            },
+            None => {
+                let interval = self.interval.expect("no timing set");
+                (interval, now + interval, false)
+            }
+        };
 
         Timer {
             func: self.func,
             value: self.initial,
             interval,
-            interval_ns: interval.as_nanos(),
+            period: interval,
             next_tick,
+            last_tick: now,
+            schedule: self.schedule,
+            repeat: self.repeat,
+            catch_up: self.catch_up,
+            expired,
+            fire_count: 0,
+            time_scale: 1.0,
+            paused_at: None,
         }
     }
+
+    /// Start the timer, reading the start time from a time source
+    #[inline]
+    pub fn start_from<S: TimeSource>(self, source: &S) -> Timer<F, V, R> {
+        self.start(source.now())
+    }
 }
 
+/// An interval timer
+///
+/// Executes a function, which may mutate its environment, when the timer is
+/// updated after its interval has elapsed. Constructed using `Timer::apply`.
 #[derive(Debug)]
 pub struct Timer<F, V, R>
 where
-    F: Fn(time::Duration, &mut V) -> R,
+    F: FnMut(time::Duration, &mut V) -> R,
 {
     func: F,
     value: V,
     interval: time::Duration,
-    interval_ns: u128,
+    /// Interval in real time, i.e. with the time scale applied
+    period: time::Duration,
     next_tick: time::Instant,
+    /// Previous deadline, used by scheduled timers
+    last_tick: time::Instant,
+    schedule: Option<DynSchedule>,
+    repeat: bool,
+    catch_up: bool,
+    expired: bool,
+    fire_count: u64,
+    time_scale: f64,
+    paused_at: Option<time::Instant>,
 }
 
 impl<F, V, R> Timer<F, V, R>
 where
-    F: Fn(time::Duration, &mut V) -> R,
+    F: FnMut(time::Duration, &mut V) -> R,
 {
     /// Construct new timer
     ///
@@ -128,15 +227,205 @@ where
     }
 
     /// Get timer interval
+    ///
+    /// For scheduled timers, this is the time between the previous and the
+    /// next deadline.
     pub fn interval(&self) -> time::Duration {
         self.interval
     }
 
+    /// Get the instant at which the timer fires next
+    ///
+    /// Returns `None` if the timer has expired.
+    #[inline]
+    pub fn next_deadline(&self) -> Option<time::Instant> {
+        if self.expired {
+            None
+        } else {
+            Some(self.next_tick)
+        }
+    }
+
+    /// Get the time remaining until the timer fires next
+    ///
+    /// Returns zero if the timer is due or has expired. Does not decrease
+    /// while the timer is paused.
+    #[inline]
+    pub fn remaining(&self, now: time::Instant) -> time::Duration {
+        let now = self.paused_at.unwrap_or(now);
+
+        match self.next_deadline() {
+            Some(deadline) => deadline.saturating_duration_since(now),
+            None => time::Duration::from_secs(0),
+        }
+    }
+
+    /// Get the progress towards the next execution, from 0 to 1
+    ///
+    /// Returns 1 if the timer is due or has expired.
+    #[inline]
+    pub fn progress(&self, now: time::Instant) -> f64 {
+        let period_ns = self.period.as_nanos();
+
+        if period_ns == 0 {
+            return 1.0;
+        }
+
+        let remaining = self.remaining(now).as_nanos().min(period_ns);
+        1.0 - remaining as f64 / period_ns as f64
+    }
+
+    /// Get the number of times the timer has fired
+    #[inline]
+    pub fn fire_count(&self) -> u64 {
+        self.fire_count
+    }
+
+    /// Pause the timer
+    ///
+    /// A paused timer does not fire. Does nothing if the timer is already
+    /// paused.
+    #[inline]
+    pub fn pause(&mut self, now: time::Instant) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(now);
+        }
+    }
+
+    /// Resume a paused timer
+    ///
+    /// The next execution is delayed by the time spent paused. Scheduled
+    /// timers follow their schedule instead, firing at its first deadline
+    /// after `now`; deadlines passed while paused are skipped. Does nothing
+    /// if the timer is not paused.
+    #[inline]
+    pub fn resume(&mut self, now: time::Instant) {
+        let paused_at = match self.paused_at.take() {
+            Some(paused_at) => paused_at,
+            None => return,
+        };
+
+        if let Some(schedule) = self.schedule.as_mut() {
+            if !self.expired && self.next_tick <= now {
+                match schedule.0.next_after(now) {
+                    Some(next_tick) => self.next_tick = next_tick,
+                    None => self.expired = true,
+                }
+            }
+            return;
+        }
+
+        let paused = now.saturating_duration_since(paused_at);
+        self.next_tick += paused;
+        self.last_tick += paused;
+    }
+
+    /// Check whether the timer is paused
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Get the speed of the timer relative to real time
+    #[inline]
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    /// Change the speed of the timer relative to real time
+    ///
+    /// A scale of 2 halves the real time between executions, a scale of 0.5
+    /// doubles it. The time remaining until the next execution is scaled
+    /// accordingly. The elapsed time passed to the function is scaled as
+    /// well, i.e. it reflects the time passed at the timer's speed.
+    ///
+    /// Scheduled timers always follow their schedule, ignoring the scale.
+    ///
+    /// Panics if `scale` is not a positive, finite number.
+    pub fn set_time_scale(&mut self, scale: f64, now: time::Instant) {
+        assert!(
+            scale > 0.0 && scale.is_finite(),
+            "time scale must be positive and finite"
+        );
+
+        if self.schedule.is_some() {
+            self.time_scale = scale;
+            return;
+        }
+
+        let now = self.paused_at.unwrap_or(now);
+        let remaining = self.next_tick.saturating_duration_since(now);
+
+        self.next_tick = now + remaining.mul_f64(self.time_scale / scale);
+        // short intervals must not round down to a zero period
+        self.period = self
+            .interval
+            .div_f64(scale)
+            .max(time::Duration::from_nanos(1));
+        self.time_scale = scale;
+    }
+
     /// Replace the stored value
     pub fn set_value(&mut self, value: V) {
         self.value = value;
     }
 
+    /// Check whether the timer repeats
+    ///
+    /// Timers created using `once` do not repeat.
+    #[inline]
+    pub fn is_repeating(&self) -> bool {
+        self.repeat
+    }
+
+    /// Check whether a one-shot timer has fired
+    ///
+    /// An expired timer no longer fires until it is restarted. Repeating
+    /// timers never expire, scheduled timers expire when their schedule ends.
+    #[inline]
+    pub fn is_expired(&self) -> bool {
+        self.expired
+    }
+
+    /// Return the timer to its initial state, as if it was started at `now`
+    ///
+    /// Replaces the stored value with `initial`, clears the fire count, the
+    /// time scale and a pause, then rearms the timer like `restart`.
+    pub fn reset(&mut self, initial: V, now: time::Instant) {
+        self.value = initial;
+        self.fire_count = 0;
+        self.time_scale = 1.0;
+        self.period = self.interval;
+        self.paused_at = None;
+        self.restart(now);
+    }
+
+    /// Rearm the timer
+    ///
+    /// The timer will fire again after one interval has passed since `now`,
+    /// regardless of whether or not it had expired. Scheduled timers fire at
+    /// the schedule's first deadline after `now`. The stored value is kept. A paused timer stays paused, as if it was paused at
+    /// `now`.
+    pub fn restart(&mut self, now: time::Instant) {
+        self.last_tick = now;
+        self.expired = false;
+        if self.paused_at.is_some() {
+            self.paused_at = Some(now);
+        }
+
+        match self.schedule.as_mut() {
+            Some(schedule) => match schedule.0.next_after(now) {
+                Some(next_tick) => {
+                    self.next_tick = next_tick;
+                    self.interval = next_tick.saturating_duration_since(now);
+                    self.period = self.interval;
+                }
+                None => self.expired = true,
+            },
+            None => self.next_tick = now + self.period,
+        }
+    }
+
     /// Execute function and calculate next execution instant
     ///
     /// If `now` is less than the next execution instant, i.e. execution
@@ -144,30 +433,168 @@ where
     ///
     /// Otherwise, the the next execution instant is calculated, the function
     /// called and the new value returned.
+    ///
+    /// One-shot timers expire after firing and return `None` afterwards.
+    /// Paused timers never fire.
+    #[inline]
     pub fn update(&mut self, now: time::Instant) -> Option<R> {
+        self.update_with_count(now).map(|(_, result)| result)
+    }
+
+    /// Execute function if due, returning the number of elapsed intervals
+    ///
+    /// Like `update`, but also returns how many intervals have elapsed since
+    /// the timer last fired, which is more than one if the update is late. For
+    /// one-shot timers, this is always 1, for scheduled timers it is the
+    /// number of deadlines passed.
+    pub fn update_with_count(&mut self, now: time::Instant) -> Option<(u128, R)> {
         // check if timer needs to fire
-        if self.next_tick > now {
+        if self.expired || self.paused_at.is_some() || self.next_tick > now {
             return None;
         }
 
+        if self.schedule.is_some() {
+            return Some(self.update_scheduled(now));
+        }
+
+        self.expired = !self.repeat;
+
         // calculate delta and update tick
-        let dt = now - self.next_tick + self.interval;
+        let dt = now - self.next_tick + self.period;
 
         // calculate how many ticks we already passed
-        let dt_ns = dt.as_nanos();
-        let ticks = dt_ns / self.interval_ns;
+        let ticks = if self.repeat {
+            dt.as_nanos() / self.period.as_nanos()
+        } else {
+            1
+        };
 
         // next tick
-        self.next_tick += self.interval * ticks as u32;
+        self.next_tick += duration_multiple(self.period, ticks);
+
+        // handle tick(s), update value
+        if self.catch_up {
+            for _ in 1..ticks {
+                (self.func)(self.interval, &mut self.value);
+            }
 
-        // handle tick, update value
-        Some((&self.func)(dt, &mut self.value))
+            self.fire_count = self
+                .fire_count
+                .saturating_add(u64::try_from(ticks).unwrap_or(u64::MAX));
+            return Some((ticks, (self.func)(self.interval, &mut self.value)));
+        }
+
+        let dt = if self.time_scale == 1.0 {
+            dt
+        } else {
+            dt.mul_f64(self.time_scale)
+        };
+
+        self.fire_count += 1;
+        Some((ticks, (self.func)(dt, &mut self.value)))
+    }
+
+    /// Fires a scheduled timer that is due at `now`.
+    fn update_scheduled(&mut self, now: time::Instant) -> (u128, R) {
+        let schedule = &mut self.schedule.as_mut().expect("timer has no schedule").0;
+
+        let mut ticks = 0;
+        let mut result = None;
+        let mut last_due = self.next_tick;
+        let mut due = Some(self.next_tick);
+
+        while let Some(tick) = due.filter(|&tick| tick <= now) {
+            ticks += 1;
+            last_due = tick;
+
+            if self.catch_up {
+                result = Some((self.func)(tick - self.last_tick, &mut self.value));
+                self.fire_count += 1;
+                self.last_tick = tick;
+            }
+
+            due = schedule.next_after(tick);
+        }
+
+        let result = match result {
+            Some(result) => result,
+            None => {
+                self.fire_count += 1;
+                (self.func)(now - self.last_tick, &mut self.value)
+            }
+        };
+        self.last_tick = last_due;
+
+        match due {
+            Some(next_tick) => {
+                self.next_tick = next_tick;
+                self.interval = next_tick.saturating_duration_since(last_due);
+                self.period = self.interval;
+            }
+            None => self.expired = true,
+        }
+
+        (ticks, result)
+    }
+
+    /// Execute function if due, reading the current time from a time source
+    ///
+    /// See `update` for details.
+    #[inline]
+    pub fn poll<S: TimeSource>(&mut self, source: &S) -> Option<R> {
+        self.update(source.now())
+    }
+
+    /// Box the timer's function, erasing its type
+    ///
+    /// See `BoxedTimer`.
+    #[inline]
+    pub fn boxed(self) -> BoxedTimer<V, R>
+    where
+        F: Send + 'static,
+    {
+        self.map_func(|func| Box::new(func) as _)
+    }
+
+    /// Box the timer's function, erasing its type, without requiring `Send`
+    ///
+    /// See `LocalBoxedTimer`.
+    #[inline]
+    pub fn boxed_local(self) -> LocalBoxedTimer<V, R>
+    where
+        F: 'static,
+    {
+        self.map_func(|func| Box::new(func) as _)
+    }
+
+    /// Replaces the timer's function by `wrap(func)`, keeping all state.
+    #[inline]
+    fn map_func<G, W>(self, wrap: W) -> Timer<G, V, R>
+    where
+        G: FnMut(time::Duration, &mut V) -> R,
+        W: FnOnce(F) -> G,
+    {
+        Timer {
+            func: wrap(self.func),
+            value: self.value,
+            interval: self.interval,
+            period: self.period,
+            next_tick: self.next_tick,
+            last_tick: self.last_tick,
+            schedule: self.schedule,
+            repeat: self.repeat,
+            catch_up: self.catch_up,
+            expired: self.expired,
+            fire_count: self.fire_count,
+            time_scale: self.time_scale,
+            paused_at: self.paused_at,
+        }
     }
 }
 
 impl<F, V: Clone, R> Timer<F, V, R>
 where
-    F: Fn(time::Duration, &mut V) -> R,
+    F: FnMut(time::Duration, &mut V) -> R,
 {
     /// Returns a copy of the value stored inside timer.
     #[inline]
@@ -176,9 +603,15 @@ where
     }
 }
 
+impl fmt::Debug for DynSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Schedule")
+    }
+}
+
 impl<F, V, R> AsRef<V> for Timer<F, V, R>
 where
-    F: Fn(time::Duration, &mut V) -> R,
+    F: FnMut(time::Duration, &mut V) -> R,
 {
     #[inline(always)]
     fn as_ref(&self) -> &V {
@@ -188,7 +621,7 @@ where
 
 impl<F, V, R> AsMut<V> for Timer<F, V, R>
 where
-    F: Fn(time::Duration, &mut V) -> R,
+    F: FnMut(time::Duration, &mut V) -> R,
 {
     #[inline(always)]
     fn as_mut(&mut self) -> &mut V {
@@ -268,6 +701,446 @@ mod tests {
         assert_eq!(timer.value(), 3);
     }
 
+    #[test]
+    fn one_shot_fires_once() {
+        let now = time::Instant::now();
+        let mut timer = Timer::apply(|_, count| *count += 1, 0)
+            .once(time::Duration::from_millis(50))
+            .start(now);
+
+        assert!(!timer.is_repeating());
+        assert!(timer
+            .update(now + time::Duration::from_millis(49))
+            .is_none());
+        assert!(!timer.is_expired());
+
+        assert!(timer
+            .update(now + time::Duration::from_millis(50))
+            .is_some());
+        assert!(timer.is_expired());
+        assert!(timer
+            .update(now + time::Duration::from_millis(100))
+            .is_none());
+        assert!(timer
+            .update(now + time::Duration::from_millis(1000))
+            .is_none());
+        assert_eq!(timer.value(), 1);
+
+        let later = now + time::Duration::from_millis(1000);
+        timer.restart(later);
+        assert!(!timer.is_expired());
+        assert!(timer
+            .update(later + time::Duration::from_millis(49))
+            .is_none());
+        assert!(timer
+            .update(later + time::Duration::from_millis(50))
+            .is_some());
+        assert_eq!(timer.value(), 2);
+        assert!(timer.is_expired());
+    }
+
+    #[test]
+    fn introspection() {
+        let now = time::Instant::now();
+        let ms = |n| now + time::Duration::from_millis(n);
+        let mut timer = Timer::apply(|_, _| (), ())
+            .every(time::Duration::from_millis(100))
+            .start(now);
+
+        assert_eq!(timer.next_deadline(), Some(ms(100)));
+        assert_eq!(timer.remaining(ms(25)), time::Duration::from_millis(75));
+        assert_eq!(timer.progress(now), 0.0);
+        assert_eq!(timer.progress(ms(25)), 0.25);
+        assert_eq!(timer.progress(ms(150)), 1.0);
+        assert_eq!(timer.fire_count(), 0);
+
+        timer.update(ms(150));
+        assert_eq!(timer.next_deadline(), Some(ms(200)));
+        assert_eq!(timer.progress(ms(150)), 0.5);
+        assert_eq!(timer.fire_count(), 1);
+
+        let mut once = Timer::apply(|_, _| (), ())
+            .once(time::Duration::from_millis(100))
+            .start(now);
+        once.update(ms(100));
+        assert_eq!(once.next_deadline(), None);
+        assert_eq!(once.remaining(ms(100)), time::Duration::from_secs(0));
+        assert_eq!(once.progress(ms(100)), 1.0);
+        assert_eq!(once.fire_count(), 1);
+    }
+
+    #[test]
+    fn reset_rearms() {
+        let now = time::Instant::now();
+        let ms = |n| now + time::Duration::from_millis(n);
+        let mut timer = Timer::apply(|_, count| *count += 1, 0)
+            .every(time::Duration::from_millis(100))
+            .start(now);
+
+        timer.update(ms(250));
+        timer.pause(ms(260));
+        timer.set_time_scale(2.0, ms(260));
+        assert_eq!(timer.value(), 1);
+
+        timer.reset(10, ms(1000));
+        assert!(!timer.is_paused());
+        assert_eq!(timer.time_scale(), 1.0);
+        assert_eq!(timer.value(), 10);
+        assert_eq!(timer.fire_count(), 0);
+        assert_eq!(timer.progress(ms(1000)), 0.0);
+        assert_eq!(timer.next_deadline(), Some(ms(1100)));
+        assert!(timer.update(ms(1099)).is_none());
+        assert_eq!(timer.update(ms(1100)), Some(()));
+        assert_eq!(timer.value(), 11);
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let now = time::Instant::now();
+        let ms = |n| now + time::Duration::from_millis(n);
+        let mut timer = Timer::apply(|_, count| *count += 1, 0)
+            .every(time::Duration::from_millis(100))
+            .start(now);
+
+        timer.pause(ms(40));
+        assert!(timer.update(ms(500)).is_none());
+        assert_eq!(timer.remaining(ms(500)), time::Duration::from_millis(60));
+
+        timer.resume(ms(500));
+        assert!(!timer.is_paused());
+        assert_eq!(timer.next_deadline(), Some(ms(560)));
+        assert!(timer.update(ms(559)).is_none());
+        assert!(timer.update(ms(560)).is_some());
+        assert_eq!(timer.value(), 1);
+    }
+
+    #[test]
+    fn time_scale_keeps_period_nonzero() {
+        let now = time::Instant::now();
+        let mut timer = Timer::apply(|_, _| (), ())
+            .every(time::Duration::from_nanos(1))
+            .start(now);
+
+        timer.set_time_scale(3.0, now);
+        assert_eq!(timer.period, time::Duration::from_nanos(1));
+        assert!(timer.update(now + time::Duration::from_nanos(10)).is_some());
+    }
+
+    #[test]
+    fn restart_while_paused() {
+        let now = time::Instant::now();
+        let ms = |n| now + time::Duration::from_millis(n);
+        let mut timer = Timer::apply(|_, _| (), ())
+            .once(time::Duration::from_millis(100))
+            .start(now);
+
+        timer.pause(now);
+        timer.restart(ms(1000));
+        assert!(timer.is_paused());
+
+        timer.resume(ms(1000));
+        assert_eq!(timer.next_deadline(), Some(ms(1100)));
+    }
+
+    #[test]
+    fn time_scale() {
+        let now = time::Instant::now();
+        let ms = |n| now + time::Duration::from_millis(n);
+        let mut timer = Timer::apply(|dt, _| dt, ())
+            .every(time::Duration::from_millis(100))
+            .start(now);
+
+        // slow motion after half the interval
+        timer.set_time_scale(0.5, ms(50));
+        assert_eq!(timer.next_deadline(), Some(ms(150)));
+        assert_eq!(timer.progress(ms(50)), 0.5);
+
+        // elapsed time is reported in scaled time
+        assert_eq!(
+            timer.update(ms(150)),
+            Some(time::Duration::from_millis(100))
+        );
+        assert_eq!(timer.next_deadline(), Some(ms(350)));
+
+        timer.set_time_scale(2.0, ms(250));
+        assert_eq!(timer.next_deadline(), Some(ms(275)));
+        assert_eq!(
+            timer.update(ms(275)),
+            Some(time::Duration::from_millis(100))
+        );
+        assert_eq!(timer.next_deadline(), Some(ms(325)));
+    }
+
+    #[test]
+    fn reports_elapsed_intervals() {
+        let now = time::Instant::now();
+        let ms = |n| now + time::Duration::from_millis(n);
+        let mut timer = Timer::apply(|dt, _| dt, ())
+            .every(time::Duration::from_millis(50))
+            .start(now);
+
+        assert_eq!(
+            timer.update_with_count(ms(50)),
+            Some((1, time::Duration::from_millis(50)))
+        );
+        assert_eq!(
+            timer.update_with_count(ms(275)),
+            Some((4, time::Duration::from_millis(225)))
+        );
+        assert_eq!(timer.update_with_count(ms(299)), None);
+        assert_eq!(timer.fire_count(), 2);
+    }
+
+    #[test]
+    fn catches_up_on_missed_intervals() {
+        let now = time::Instant::now();
+        let mut timer = Timer::apply(
+            |dt, count| {
+                assert_eq!(dt, time::Duration::from_millis(50));
+                *count += 1;
+                *count
+            },
+            0,
+        )
+        .every(time::Duration::from_millis(50))
+        .catch_up()
+        .start(now);
+
+        assert_eq!(
+            timer.update(now + time::Duration::from_millis(100)),
+            Some(2)
+        );
+        assert_eq!(
+            timer.update_with_count(now + time::Duration::from_millis(10000)),
+            Some((198, 200))
+        );
+        assert_eq!(timer.value(), 200);
+        assert_eq!(timer.fire_count(), 200);
+    }
+
+    #[test]
+    fn mutates_captured_state() {
+        let now = time::Instant::now();
+        let mut log = Vec::new();
+        let mut timer = Timer::apply(|dt, _| log.push(dt), ())
+            .every(time::Duration::from_millis(10))
+            .start(now);
+
+        timer.update(now + time::Duration::from_millis(10));
+        timer.update(now + time::Duration::from_millis(25));
+
+        assert_eq!(
+            log,
+            vec![
+                time::Duration::from_millis(10),
+                time::Duration::from_millis(15)
+            ]
+        );
+    }
+
+    #[test]
+    fn boxed_timers_in_collection() {
+        let now = time::Instant::now();
+        let mut calls = 0;
+        let mut timers: Vec<BoxedTimer<u32, &'static str>> = vec![
+            Timer::apply(
+                |_, n| {
+                    *n += 1;
+                    "tick"
+                },
+                0,
+            )
+            .every(time::Duration::from_millis(10))
+            .start(now)
+            .boxed(),
+            Timer::apply(
+                move |_, n| {
+                    calls += 1;
+                    *n = calls * 100;
+                    "boom"
+                },
+                0,
+            )
+            .once(time::Duration::from_millis(15))
+            .start(now)
+            .boxed(),
+        ];
+
+        // boxed timers can be updated from another thread
+        let later = now + time::Duration::from_millis(20);
+        let (fired, timers) = std::thread::spawn(move || {
+            let fired: Vec<_> = timers.iter_mut().filter_map(|t| t.update(later)).collect();
+            (fired, timers)
+        })
+        .join()
+        .unwrap();
+        assert_eq!(fired, vec!["tick", "boom"]);
+        assert_eq!(timers[0].value(), 1);
+        assert_eq!(timers[1].value(), 100);
+    }
+
+    #[test]
+    fn local_boxed_timers() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let now = time::Instant::now();
+        let fired = Rc::new(Cell::new(0));
+        let counter = fired.clone();
+
+        let mut timers: Vec<LocalBoxedTimer<(), ()>> =
+            vec![Timer::apply(move |_, _| counter.set(counter.get() + 1), ())
+                .every(time::Duration::from_millis(10))
+                .start(now)
+                .boxed_local()];
+
+        timers[0].update(now + time::Duration::from_millis(10));
+        assert_eq!(fired.get(), 1);
+    }
+
+    /// Fires at fixed offsets from a start instant.
+    struct Offsets(time::Instant, Vec<u64>);
+
+    impl Schedule for Offsets {
+        fn next_after(&mut self, after: time::Instant) -> Option<time::Instant> {
+            self.1
+                .iter()
+                .map(|&ms| self.0 + time::Duration::from_millis(ms))
+                .find(|&deadline| deadline > after)
+        }
+    }
+
+    #[test]
+    fn timers_are_send() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let now = time::Instant::now();
+        let builder = Timer::apply(|_, _| (), ()).schedule(Offsets(now, vec![10]));
+        assert_send(&builder);
+        assert_send(&builder.start(now));
+        assert_send(
+            &Timer::apply(|_, _| (), ())
+                .every(time::Duration::from_millis(10))
+                .start(now),
+        );
+    }
+
+    #[test]
+    fn follows_schedule() {
+        let now = time::Instant::now();
+        let ms = |n| now + time::Duration::from_millis(n);
+        let mut timer = Timer::apply(|dt, _| dt, ())
+            .schedule(Offsets(now, vec![10, 30, 40, 50, 100]))
+            .start(now);
+
+        assert_eq!(timer.next_deadline(), Some(ms(10)));
+        assert_eq!(timer.interval(), time::Duration::from_millis(10));
+        assert_eq!(
+            timer.update_with_count(ms(12)),
+            Some((1, time::Duration::from_millis(12)))
+        );
+        assert_eq!(timer.next_deadline(), Some(ms(30)));
+        assert_eq!(timer.interval(), time::Duration::from_millis(20));
+
+        // two deadlines passed
+        assert_eq!(
+            timer.update_with_count(ms(45)),
+            Some((2, time::Duration::from_millis(35)))
+        );
+        assert_eq!(timer.next_deadline(), Some(ms(50)));
+
+        assert!(timer.update(ms(60)).is_some());
+        assert!(!timer.is_expired());
+        assert!(timer.update(ms(100)).is_some());
+        assert!(timer.is_expired());
+        assert_eq!(timer.fire_count(), 4);
+
+        timer.restart(ms(20));
+        assert_eq!(timer.next_deadline(), Some(ms(30)));
+    }
+
+    #[test]
+    fn resume_follows_schedule() {
+        let now = time::Instant::now();
+        let ms = |n| now + time::Duration::from_millis(n);
+        let mut timer = Timer::apply(|_, _| (), ())
+            .schedule(Offsets(now, vec![10, 30, 100]))
+            .start(now);
+
+        // a deadline still ahead is kept, not shifted by the pause
+        timer.pause(ms(0));
+        timer.resume(ms(5));
+        assert_eq!(timer.next_deadline(), Some(ms(10)));
+
+        // deadlines passed while paused are skipped
+        timer.pause(ms(5));
+        timer.resume(ms(50));
+        assert_eq!(timer.next_deadline(), Some(ms(100)));
+        assert!(timer.update(ms(99)).is_none());
+        assert!(timer.update(ms(100)).is_some());
+
+        timer.restart(ms(0));
+        timer.pause(ms(0));
+        timer.resume(ms(200));
+        assert!(timer.is_expired());
+    }
+
+    #[test]
+    fn schedule_catches_up() {
+        let now = time::Instant::now();
+        let mut timer = Timer::apply(|dt, log: &mut Vec<_>| log.push(dt), Vec::new())
+            .schedule(Offsets(now, vec![10, 30, 60]))
+            .catch_up()
+            .start(now);
+
+        assert_eq!(
+            timer.update_with_count(now + time::Duration::from_millis(100)),
+            Some((3, ()))
+        );
+        assert_eq!(
+            timer.value(),
+            vec![
+                time::Duration::from_millis(10),
+                time::Duration::from_millis(20),
+                time::Duration::from_millis(30),
+            ]
+        );
+        assert!(timer.is_expired());
+    }
+
+    #[test]
+    fn update_past_u32_range() {
+        let start = time::Instant::now();
+        let ms = |n: u64| start + time::Duration::from_millis(n);
+        let mut timer = Timer::apply(|_, _| (), ())
+            .every(time::Duration::from_millis(1))
+            .start(start);
+
+        // about 50 days of missed intervals at 1 kHz
+        let n = u64::from(u32::MAX) + 10;
+        let (ticks, _) = timer.update_with_count(ms(n)).unwrap();
+        assert_eq!(ticks, u128::from(n));
+        assert_eq!(timer.next_deadline(), Some(ms(n + 1)));
+
+        assert!(timer.update(ms(n)).is_none());
+        assert_eq!(timer.update_with_count(ms(n + 1)).unwrap().0, 1);
+    }
+
+    #[test]
+    fn polls_time_source() {
+        let mock = crate::time_source::MockClock::new();
+        let mut timer = Timer::apply(|_, count| *count += 1, 0)
+            .every(time::Duration::from_millis(50))
+            .start_from(&mock);
+
+        timer.poll(&mock);
+        assert_eq!(timer.value(), 0);
+
+        mock.advance(time::Duration::from_millis(50));
+        timer.poll(&mock);
+        assert_eq!(timer.value(), 1);
+    }
+
     #[test]
     fn test_just_called() {
         let now = time::Instant::now();
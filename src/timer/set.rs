@@ -0,0 +1,530 @@
+//! Sets of timers.
+
+use super::Timer;
+use crate::clock::duration_multiple;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time;
+
+/// A set of many timers
+///
+/// Instead of polling every timer individually, all timers are kept ordered
+/// by their deadline, so `advance` only touches the ones that are due.
+/// Inserting and cancelling is `O(log n)`; cancelled timers are removed
+/// lazily.
+///
+/// Each timer carries a value of type `T`, e.g. an event or the id of the
+/// entity it belongs to. Timers are referred to by `TimerHandle`s, which stay
+/// unique even after the timer they refer to is gone.
+///
+/// ```
+/// use std::time;
+/// use ticktock::timer::TimerSet;
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Event {
+///     Spawn,
+///     Autosave,
+/// }
+///
+/// let now = time::Instant::now();
+/// let mut timers = TimerSet::new();
+///
+/// let spawn = timers.insert_at(now + time::Duration::from_secs(3), Event::Spawn);
+/// let autosave = timers.insert_every(now, time::Duration::from_secs(2), Event::Autosave);
+///
+/// // repeating timers keep their value in the set
+/// let due = timers.advance(now + time::Duration::from_secs(2));
+/// assert_eq!(due, vec![(autosave, None)]);
+/// assert_eq!(timers.get(autosave), Some(&Event::Autosave));
+///
+/// // one-shot timers hand it back
+/// let due = timers.advance(now + time::Duration::from_secs(3));
+/// assert_eq!(due, vec![(spawn, Some(Event::Spawn))]);
+/// ```
+///
+/// The set can also hold `Timer`s, e.g. `BoxedTimer`s with different
+/// functions, see `insert_timer` and `update`.
+#[derive(Debug)]
+pub struct TimerSet<T> {
+    /// Storage of all timers, indexed by handle.
+    slots: Vec<Slot<T>>,
+    /// Indices of unused slots.
+    free: Vec<usize>,
+    /// Deadlines, earliest first. May contain stale entries.
+    queue: BinaryHeap<Reverse<(time::Instant, usize, u32)>>,
+    /// Number of live timers.
+    len: usize,
+}
+
+/// A handle to a timer inside a `TimerSet`
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TimerHandle {
+    /// Index of the slot.
+    index: usize,
+    /// Generation of the slot when the timer was inserted.
+    generation: u32,
+}
+
+/// Storage for a single timer, reused after the timer is gone.
+#[derive(Debug)]
+struct Slot<T> {
+    /// Incremented every time the slot is vacated.
+    generation: u32,
+    /// The timer, if any.
+    entry: Option<Entry<T>>,
+}
+
+/// A timer inside a set.
+#[derive(Debug)]
+struct Entry<T> {
+    /// The timer's value.
+    value: T,
+    /// Next time the timer fires.
+    deadline: time::Instant,
+    /// Interval of repeating timers.
+    interval: Option<time::Duration>,
+}
+
+impl<T> TimerSet<T> {
+    /// Creates a new, empty set
+    #[inline]
+    pub fn new() -> TimerSet<T> {
+        TimerSet {
+            slots: Vec::new(),
+            free: Vec::new(),
+            queue: BinaryHeap::new(),
+            len: 0,
+        }
+    }
+
+    /// Number of timers in the set
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the set is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds a timer firing once at `deadline`
+    ///
+    /// The timer is removed from the set once it fired.
+    #[inline]
+    pub fn insert_at(&mut self, deadline: time::Instant, value: T) -> TimerHandle {
+        self.insert(Entry {
+            value,
+            deadline,
+            interval: None,
+        })
+    }
+
+    /// Adds a timer firing every `interval`, starting at `now`
+    ///
+    /// Like `Timer`, ticks that have been missed entirely are skipped.
+    ///
+    /// Panics if `interval` is zero.
+    #[inline]
+    pub fn insert_every(
+        &mut self,
+        now: time::Instant,
+        interval: time::Duration,
+        value: T,
+    ) -> TimerHandle {
+        assert!(
+            interval > time::Duration::from_secs(0),
+            "interval must not be zero"
+        );
+
+        self.insert(Entry {
+            value,
+            deadline: now + interval,
+            interval: Some(interval),
+        })
+    }
+
+    /// Check whether the timer referred to by `handle` is still in the set
+    #[inline]
+    pub fn contains(&self, handle: TimerHandle) -> bool {
+        self.entry(handle).is_some()
+    }
+
+    /// Get a reference to a timer's value
+    #[inline]
+    pub fn get(&self, handle: TimerHandle) -> Option<&T> {
+        self.entry(handle).map(|entry| &entry.value)
+    }
+
+    /// Get a mutable reference to a timer's value
+    #[inline]
+    pub fn get_mut(&mut self, handle: TimerHandle) -> Option<&mut T> {
+        self.slots
+            .get_mut(handle.index)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.entry.as_mut())
+            .map(|entry| &mut entry.value)
+    }
+
+    /// Get the next time the timer referred to by `handle` fires
+    #[inline]
+    pub fn deadline(&self, handle: TimerHandle) -> Option<time::Instant> {
+        self.entry(handle).map(|entry| entry.deadline)
+    }
+
+    /// Moves the next deadline of a timer
+    ///
+    /// Repeating timers continue in their interval from `deadline`. Returns
+    /// `false` if the timer is no longer in the set.
+    pub fn reschedule(&mut self, handle: TimerHandle, deadline: time::Instant) -> bool {
+        let entry = match self.slots.get_mut(handle.index) {
+            Some(Slot {
+                generation,
+                entry: Some(entry),
+            }) if *generation == handle.generation => entry,
+            _ => return false,
+        };
+
+        entry.deadline = deadline;
+        self.queue
+            .push(Reverse((deadline, handle.index, handle.generation)));
+        self.purge();
+        true
+    }
+
+    /// Removes a timer from the set, returning its value
+    ///
+    /// Returns `None` if the timer is no longer in the set.
+    pub fn cancel(&mut self, handle: TimerHandle) -> Option<T> {
+        if !self.contains(handle) {
+            return None;
+        }
+
+        let entry = self.remove(handle.index);
+        self.purge();
+        Some(entry.value)
+    }
+
+    /// Get the earliest deadline of all timers in the set
+    #[inline]
+    pub fn next_deadline(&self) -> Option<time::Instant> {
+        self.queue.peek().map(|Reverse((deadline, _, _))| *deadline)
+    }
+
+    /// Adds a new entry.
+    fn insert(&mut self, entry: Entry<T>) -> TimerHandle {
+        let deadline = entry.deadline;
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.slots[index].entry = Some(entry);
+                index
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    entry: Some(entry),
+                });
+                self.slots.len() - 1
+            }
+        };
+
+        let generation = self.slots[index].generation;
+        self.queue.push(Reverse((deadline, index, generation)));
+        self.len += 1;
+
+        TimerHandle { index, generation }
+    }
+
+    /// Get the entry referred to by `handle`, if still alive.
+    #[inline]
+    fn entry(&self, handle: TimerHandle) -> Option<&Entry<T>> {
+        self.slots
+            .get(handle.index)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.entry.as_ref())
+    }
+
+    /// Removes the live entry at `index`, vacating its slot.
+    #[inline]
+    fn remove(&mut self, index: usize) -> Entry<T> {
+        let slot = &mut self.slots[index];
+        let entry = slot.entry.take().expect("removing empty slot");
+
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(index);
+        self.len -= 1;
+
+        entry
+    }
+
+    /// Discards stale deadlines, so that the earliest queued deadline is
+    /// always current. Rebuilds the queue if it is mostly stale.
+    fn purge(&mut self) {
+        while let Some(&Reverse((deadline, index, generation))) = self.queue.peek() {
+            if is_current(&self.slots, deadline, index, generation) {
+                break;
+            }
+            self.queue.pop();
+        }
+
+        if self.queue.len() > 2 * self.len + 16 {
+            let slots = &self.slots;
+            self.queue
+                .retain(|&Reverse((deadline, index, generation))| {
+                    is_current(slots, deadline, index, generation)
+                });
+        }
+    }
+}
+
+impl<T> TimerSet<T> {
+    /// Fires all timers due at `now`
+    ///
+    /// Returns the handles of all timers that fired, ordered by deadline.
+    /// One-shot timers are removed from the set and their values returned
+    /// along with their handles. Repeating timers are rescheduled and keep
+    /// their values in the set, `None` is returned for them, see `get` and
+    /// `get_mut`.
+    pub fn advance(&mut self, now: time::Instant) -> Vec<(TimerHandle, Option<T>)> {
+        let mut fired = Vec::new();
+
+        while let Some(&Reverse((deadline, index, generation))) = self.queue.peek() {
+            if deadline > now {
+                break;
+            }
+            self.queue.pop();
+
+            if !is_current(&self.slots, deadline, index, generation) {
+                continue;
+            }
+
+            let handle = TimerHandle { index, generation };
+            let entry = self.slots[index]
+                .entry
+                .as_mut()
+                .expect("current deadline without entry");
+
+            match entry.interval {
+                Some(interval) => {
+                    // skip all ticks that have already passed
+                    let missed = (now - deadline).as_nanos() / interval.as_nanos();
+                    let next = deadline + duration_multiple(interval, missed) + interval;
+
+                    entry.deadline = next;
+                    fired.push((handle, None));
+                    self.queue.push(Reverse((next, index, generation)));
+                }
+                None => {
+                    let entry = self.remove(index);
+                    fired.push((handle, Some(entry.value)));
+                }
+            }
+        }
+
+        self.purge();
+        fired
+    }
+}
+
+impl<F, V, R> TimerSet<Timer<F, V, R>>
+where
+    F: FnMut(time::Duration, &mut V) -> R,
+{
+    /// Adds a timer, firing at its own deadlines
+    ///
+    /// The timer is driven by `update`. After changing it through `get_mut`,
+    /// e.g. restarting it, pass its new `next_deadline` to `reschedule`.
+    ///
+    /// Panics if the timer has expired.
+    #[inline]
+    pub fn insert_timer(&mut self, timer: Timer<F, V, R>) -> TimerHandle {
+        let deadline = timer.next_deadline().expect("timer has expired");
+
+        self.insert(Entry {
+            value: timer,
+            deadline,
+            interval: None,
+        })
+    }
+
+    /// Updates all timers due at `now`
+    ///
+    /// Calls `Timer::update` on every due timer and returns the handles and
+    /// results of those that fired, ordered by deadline. Timers that expired
+    /// are removed from the set. Paused timers are checked on every call
+    /// until they are resumed.
+    pub fn update(&mut self, now: time::Instant) -> Vec<(TimerHandle, R)> {
+        let mut due = Vec::new();
+
+        while let Some(&Reverse((deadline, index, generation))) = self.queue.peek() {
+            if deadline > now {
+                break;
+            }
+            self.queue.pop();
+
+            if is_current(&self.slots, deadline, index, generation) {
+                due.push(TimerHandle { index, generation });
+            }
+        }
+
+        let mut fired = Vec::new();
+        for handle in due {
+            let entry = self.slots[handle.index]
+                .entry
+                .as_mut()
+                .expect("current deadline without entry");
+
+            if let Some(result) = entry.value.update(now) {
+                fired.push((handle, result));
+            }
+
+            // requeued only after all due timers are handled, so that
+            // paused timers do not keep the loop above spinning
+            match entry.value.next_deadline() {
+                Some(deadline) => {
+                    entry.deadline = deadline;
+                    self.queue
+                        .push(Reverse((deadline, handle.index, handle.generation)));
+                }
+                None => {
+                    self.remove(handle.index);
+                }
+            }
+        }
+
+        self.purge();
+        fired
+    }
+}
+
+impl<T> Default for TimerSet<T> {
+    #[inline]
+    fn default() -> Self {
+        TimerSet::new()
+    }
+}
+
+/// Checks whether a queued deadline still belongs to a live timer.
+#[inline]
+fn is_current<T>(
+    slots: &[Slot<T>],
+    deadline: time::Instant,
+    index: usize,
+    generation: u32,
+) -> bool {
+    let slot = &slots[index];
+
+    slot.generation == generation
+        && matches!(&slot.entry, Some(entry) if entry.deadline == deadline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fires_due_timers_in_order() {
+        let now = time::Instant::now();
+        let ms = |n| now + time::Duration::from_millis(n);
+        let mut timers = TimerSet::new();
+
+        let c = timers.insert_at(ms(30), 'c');
+        let a = timers.insert_at(ms(10), 'a');
+        let b = timers.insert_at(ms(20), 'b');
+
+        assert_eq!(timers.len(), 3);
+        assert_eq!(timers.next_deadline(), Some(ms(10)));
+        assert!(timers.advance(ms(9)).is_empty());
+        assert_eq!(timers.advance(ms(25)), vec![(a, Some('a')), (b, Some('b'))]);
+
+        assert!(!timers.contains(a));
+        assert_eq!(timers.get(c), Some(&'c'));
+        assert_eq!(timers.next_deadline(), Some(ms(30)));
+        assert_eq!(timers.advance(ms(30)), vec![(c, Some('c'))]);
+        assert!(timers.is_empty());
+        assert_eq!(timers.next_deadline(), None);
+    }
+
+    #[test]
+    fn repeating_timers_skip_missed_ticks() {
+        let now = time::Instant::now();
+        let ms = |n| now + time::Duration::from_millis(n);
+        let mut timers = TimerSet::new();
+
+        let t = timers.insert_every(now, time::Duration::from_millis(10), ());
+
+        assert_eq!(timers.advance(ms(10)).len(), 1);
+        assert_eq!(timers.deadline(t), Some(ms(20)));
+
+        assert_eq!(timers.advance(ms(55)).len(), 1);
+        assert_eq!(timers.deadline(t), Some(ms(60)));
+        assert_eq!(timers.next_deadline(), Some(ms(60)));
+    }
+
+    #[test]
+    fn cancelled_timers_do_not_fire() {
+        let now = time::Instant::now();
+        let ms = |n| now + time::Duration::from_millis(n);
+        let mut timers = TimerSet::new();
+
+        let a = timers.insert_at(ms(10), 1);
+        let b = timers.insert_every(now, time::Duration::from_millis(20), 2);
+
+        assert_eq!(timers.cancel(a), Some(1));
+        assert_eq!(timers.cancel(a), None);
+        assert_eq!(timers.next_deadline(), Some(ms(20)));
+
+        // slot is reused, but the old handle stays invalid
+        let c = timers.insert_at(ms(5), 3);
+        assert!(!timers.contains(a));
+        assert_eq!(timers.get(a), None);
+
+        assert!(timers.reschedule(b, ms(100)));
+        assert_eq!(timers.advance(ms(50)), vec![(c, Some(3))]);
+        assert_eq!(timers.advance(ms(100)), vec![(b, None)]);
+        assert_eq!(timers.get(b), Some(&2));
+        assert_eq!(timers.deadline(b), Some(ms(120)));
+    }
+
+    #[test]
+    fn updates_boxed_timers() {
+        use crate::timer::BoxedTimer;
+
+        let now = time::Instant::now();
+        let ms = |n| now + time::Duration::from_millis(n);
+        let mut timers: TimerSet<BoxedTimer<u32, u32>> = TimerSet::new();
+
+        let counter = timers.insert_timer(
+            Timer::apply(
+                |_, n| {
+                    *n += 1;
+                    *n
+                },
+                0,
+            )
+            .every(time::Duration::from_millis(10))
+            .start(now)
+            .boxed(),
+        );
+        let once = timers.insert_timer(
+            Timer::apply(|_, n| *n, 42)
+                .once(time::Duration::from_millis(15))
+                .start(now)
+                .boxed(),
+        );
+
+        assert!(timers.update(ms(9)).is_empty());
+        assert_eq!(timers.update(ms(10)), vec![(counter, 1)]);
+        assert_eq!(timers.update(ms(20)), vec![(once, 42), (counter, 2)]);
+        assert!(!timers.contains(once));
+        assert_eq!(timers.next_deadline(), Some(ms(30)));
+
+        // paused timers stay in the set without firing
+        timers.get_mut(counter).unwrap().pause(ms(25));
+        assert!(timers.update(ms(100)).is_empty());
+        assert!(timers.update(ms(200)).is_empty());
+        assert_eq!(timers.get(counter).unwrap().value(), 2);
+    }
+}